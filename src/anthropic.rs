@@ -0,0 +1,270 @@
+use crate::openai::{Retry, RetryPolicy, UploadError, UploadedFile};
+use crate::uploader::{
+    file_body, send_with_retry, upload_name, RequestFailure, UploadProgress, Uploader,
+};
+use crate::RepconError;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{multipart, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::env;
+use std::future::Future;
+use std::path::Path;
+
+/// Default base URL of the Anthropic API.
+pub const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com";
+
+/// Maximum size of a file uploaded to the Anthropic Files API, in bytes.
+pub const ANTHROPIC_MAX_FILE_SIZE: u64 = 500 * 1024 * 1024;
+
+/// Version of the Anthropic API the requests are written for.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Beta opting in to the Files API.
+const FILES_API_BETA: &str = "files-api-2025-04-14";
+
+/// A file object returned by the Anthropic Files API.
+#[derive(Deserialize)]
+struct AnthropicFile {
+    id: String,
+    filename: String,
+    #[serde(default)]
+    size_bytes: u64,
+}
+impl From<AnthropicFile> for UploadedFile {
+    fn from(file: AnthropicFile) -> Self {
+        Self {
+            id: file.id,
+            filename: file.filename,
+            bytes: file.size_bytes,
+            created_at: 0,
+        }
+    }
+}
+
+/// A page of files returned by the Anthropic Files API.
+#[derive(Deserialize)]
+struct FileList {
+    data: Vec<AnthropicFile>,
+    #[serde(default)]
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+/// Uploads the output files to the Anthropic Files API.
+/// Failed requests are retried like the requests to OpenAI.
+pub struct AnthropicUploader {
+    http: reqwest::Client,
+    api_base: String,
+    retry_policy: RetryPolicy,
+}
+impl AnthropicUploader {
+    /// Constructs a new `AnthropicUploader` authenticated with the API key.
+    pub fn new(
+        api_key: &str,
+        api_base: &str,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, RepconError> {
+        let mut api_key = HeaderValue::from_str(api_key).map_err(|_| {
+            RepconError::Config("Invalid characters in the Anthropic API key".to_string())
+        })?;
+        api_key.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", api_key);
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(ANTHROPIC_VERSION),
+        );
+        headers.insert("anthropic-beta", HeaderValue::from_static(FILES_API_BETA));
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(retry_policy.timeout)
+            .build()
+            .map_err(|e| RepconError::Config(e.to_string()))?;
+        Ok(Self {
+            http,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            retry_policy,
+        })
+    }
+
+    /// Constructs a new `AnthropicUploader` authenticated with the `ANTHROPIC_API_KEY`
+    /// environment variable.
+    /// The base URL can be changed with `ANTHROPIC_BASE_URL`.
+    pub fn from_env(retry_policy: RetryPolicy) -> Result<Self, RepconError> {
+        let api_key = env::var("ANTHROPIC_API_KEY").map_err(|_| {
+            RepconError::Config("ANTHROPIC_API_KEY not found in environment.".to_string())
+        })?;
+        let api_base =
+            env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| ANTHROPIC_API_BASE.to_string());
+        Self::new(&api_key, &api_base, retry_policy)
+    }
+
+    /// Sends a request built by `build_request`, retrying the failures selected by `retry`,
+    /// and parses the JSON response.
+    async fn send<T: DeserializeOwned, Fut>(
+        &self,
+        retry: Retry,
+        build_request: impl Fn() -> Fut,
+    ) -> Result<T, RequestFailure>
+    where
+        Fut: Future<Output = Result<RequestBuilder, RequestFailure>>,
+    {
+        let (_, body) = send_with_retry(&self.retry_policy, retry, build_request, failure).await?;
+        serde_json::from_str(&body)
+            .map_err(|e| RequestFailure::new(format!("Unexpected response from Anthropic: {}", e)))
+    }
+}
+
+/// Describes a non-successful response from its error object.
+fn failure(status: StatusCode, body: &str) -> RequestFailure {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        error: ErrorDetail,
+    }
+    #[derive(Deserialize)]
+    struct ErrorDetail {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        message: Option<String>,
+    }
+
+    let (code, message) = match serde_json::from_str::<ErrorBody>(body) {
+        Ok(ErrorBody { error }) => (error.kind, error.message.unwrap_or_default()),
+        Err(_) => (None, body.trim().to_string()),
+    };
+    RequestFailure {
+        status: Some(status.as_u16()),
+        code,
+        message,
+    }
+}
+
+#[async_trait]
+impl Uploader for AnthropicUploader {
+    fn destination(&self) -> String {
+        "anthropic".to_string()
+    }
+
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        let name = upload_name(file_path)?;
+        let name = &name;
+        let url = &format!("{}/v1/files", self.api_base);
+        // A timed out upload may have created the file, so it is only retried if not processed
+        self.send::<AnthropicFile, _>(Retry::Unprocessed, || async move {
+            // The file is opened again for every attempt
+            let (contents, size) = file_body(file_path, progress)
+                .await
+                .map_err(RequestFailure::new)?;
+            let file_part = multipart::Part::stream_with_length(contents, size)
+                .file_name(name.clone())
+                .mime_str("text/plain")
+                .expect("text/plain is a valid MIME type");
+            let form = multipart::Form::new().part("file", file_part);
+            Ok(self.http.post(url).multipart(form))
+        })
+        .await
+        .map(UploadedFile::from)
+        .map_err(|e| e.into_upload_error(file_path))
+    }
+
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError> {
+        let url = &format!("{}/v1/files/{}", self.api_base, file_id);
+        self.send::<serde_json::Value, _>(
+            Retry::Always,
+            || async move { Ok(self.http.delete(url)) },
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| e.into_error(&format!("delete {}", file_id)))
+    }
+
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError> {
+        let mut files = Vec::new();
+        let mut after_id: Option<String> = None;
+        loop {
+            let url = &format!("{}/v1/files", self.api_base);
+            let after = after_id.as_deref();
+            let page: FileList = self
+                .send(Retry::Always, || async move {
+                    let mut request = self.http.get(url).query(&[("limit", "1000")]);
+                    if let Some(after_id) = after {
+                        request = request.query(&[("after_id", after_id)]);
+                    }
+                    Ok(request)
+                })
+                .await
+                .map_err(|e| e.into_error("list files"))?;
+            files.extend(page.data.into_iter().map(UploadedFile::from));
+            match page.last_id {
+                Some(last_id) if page.has_more => after_id = Some(last_id),
+                _ => return Ok(files),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod anthropic_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use std::fs;
+    use std::io;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_anthropic_upload() -> io::Result<()> {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(
+                429,
+                r#"{"type": "error", "error": {"type": "rate_limit_error", "message": "Slow down"}}"#,
+            )
+            .with_header("retry-after-ms", "20"),
+            MockResponse::new(
+                200,
+                r#"{"id": "file_011", "type": "file", "filename": "output_1.txt", "mime_type": "text/plain", "size_bytes": 5, "created_at": "2025-01-01T00:00:00Z"}"#,
+            ),
+            MockResponse::new(
+                400,
+                r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "File too large"}}"#,
+            ),
+        ])
+        .await;
+        let retry_policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..RetryPolicy::default()
+        };
+        let uploader = AnthropicUploader::new("test-key", &server.url, retry_policy)?;
+        let dir = tempdir()?;
+        let file_path = dir.path().join("output_1.txt");
+        fs::write(&file_path, "hello")?;
+
+        // The rate limited upload is retried, and the client error is not
+        let uploaded_file = uploader.upload(&file_path, None).await.unwrap();
+        assert_eq!(uploaded_file.id, "file_011");
+        assert_eq!(uploaded_file.bytes, 5);
+
+        let error = uploader.upload(&file_path, None).await.unwrap_err();
+        assert_eq!(error.status, Some(400));
+        assert_eq!(error.code.as_deref(), Some("invalid_request_error"));
+        assert_eq!(error.message, "File too large");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].path, "/v1/files");
+        assert_eq!(requests[0].header("x-api-key"), Some("test-key"));
+        assert_eq!(requests[0].header("anthropic-version"), Some("2023-06-01"));
+        assert_eq!(
+            requests[0].header("anthropic-beta"),
+            Some("files-api-2025-04-14")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use crate::openai::OpenAiClient;
+use crate::RepconError;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Model of the assistants created by `create_assistant` unless another one is given.
+pub const DEFAULT_ASSISTANT_MODEL: &str = "gpt-4o";
+
+/// An assistant object returned by the OpenAI Assistants API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Assistant {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub model: String,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    /// Resources of the tools, e.g. the files of `code_interpreter`.
+    #[serde(default)]
+    pub tool_resources: Value,
+}
+
+/// Settings of an assistant to create or update.
+/// Settings left as `None` are not changed when updating an assistant.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssistantSettings {
+    pub name: Option<String>,
+    pub instructions: Option<String>,
+    pub model: Option<String>,
+}
+impl AssistantSettings {
+    /// Adds the settings that are set to a request body.
+    fn apply_to(&self, payload: &mut Value) {
+        for (key, value) in [
+            ("name", &self.name),
+            ("instructions", &self.instructions),
+            ("model", &self.model),
+        ] {
+            if let Some(value) = value {
+                payload[key] = json!(value);
+            }
+        }
+    }
+}
+
+impl OpenAiClient {
+    /// Creates an assistant whose `file_search` tool searches the given vector store.
+    /// The model defaults to `DEFAULT_ASSISTANT_MODEL`.
+    pub async fn create_assistant(
+        &self,
+        settings: &AssistantSettings,
+        vector_store_id: &str,
+    ) -> Result<Assistant, RepconError> {
+        let mut payload = json!({
+            "model": DEFAULT_ASSISTANT_MODEL,
+            "tools": [{ "type": "file_search" }],
+            "tool_resources": file_search_resources(vector_store_id),
+        });
+        settings.apply_to(&mut payload);
+        Ok(self
+            .post_json("create assistant", "assistants", &payload)
+            .await?)
+    }
+
+    /// Points the `file_search` tool of an existing assistant at the given vector store.
+    ///
+    /// The `file_search` tool is added if the assistant does not have it yet,
+    /// and its other tools and their resources are kept.
+    pub async fn update_assistant(
+        &self,
+        assistant_id: &str,
+        settings: &AssistantSettings,
+        vector_store_id: &str,
+    ) -> Result<Assistant, RepconError> {
+        let action = format!("update assistant {}", assistant_id);
+        let path = format!("assistants/{}", assistant_id);
+        let assistant: Assistant = self.get_json(&action, &path).await?;
+
+        let mut tools = assistant.tools;
+        if !tools.iter().any(|tool| tool["type"] == "file_search") {
+            tools.push(json!({ "type": "file_search" }));
+        }
+        // The update replaces the tool resources as a whole, so only the vector stores change
+        let mut tool_resources = match assistant.tool_resources {
+            Value::Object(resources) => Value::Object(resources),
+            _ => json!({}),
+        };
+        if !tool_resources["file_search"].is_object() {
+            tool_resources["file_search"] = json!({});
+        }
+        tool_resources["file_search"]["vector_store_ids"] = json!([vector_store_id]);
+        let mut payload = json!({
+            "tools": tools,
+            "tool_resources": tool_resources,
+        });
+        settings.apply_to(&mut payload);
+        Ok(self.post_json(&action, &path, &payload).await?)
+    }
+}
+
+/// Returns the tool resources giving the `file_search` tool access to a vector store.
+fn file_search_resources(vector_store_id: &str) -> Value {
+    json!({ "file_search": { "vector_store_ids": [vector_store_id] } })
+}
+
+#[cfg(test)]
+mod assistant_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::openai::{OpenAiConfig, RetryPolicy};
+
+    fn test_client(server: &MockServer) -> OpenAiClient {
+        let config = OpenAiConfig {
+            api_base: server.url.clone(),
+            retry_policy: RetryPolicy {
+                max_attempts: 1,
+                ..RetryPolicy::default()
+            },
+            ..OpenAiConfig::new("test-key")
+        };
+        OpenAiClient::new(config).unwrap()
+    }
+
+    fn body_json(body: &[u8]) -> Value {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_assistant() {
+        let server = MockServer::start_sequence(vec![MockResponse::new(
+            200,
+            r#"{"id": "asst_1", "name": "Repo", "model": "gpt-4o", "tools": [{"type": "file_search"}]}"#,
+        )])
+        .await;
+        let settings = AssistantSettings {
+            name: Some("Repo".to_string()),
+            instructions: Some("Answer questions about the code.".to_string()),
+            model: None,
+        };
+
+        let assistant = test_client(&server)
+            .create_assistant(&settings, "vs_1")
+            .await
+            .unwrap();
+
+        assert_eq!(assistant.id, "asst_1");
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/assistants");
+        assert_eq!(
+            body_json(&requests[0].body),
+            json!({
+                "name": "Repo",
+                "instructions": "Answer questions about the code.",
+                "model": "gpt-4o",
+                "tools": [{ "type": "file_search" }],
+                "tool_resources": { "file_search": { "vector_store_ids": ["vs_1"] } },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_assistant_keeps_other_tools() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(
+                200,
+                r#"{"id": "asst_1", "name": "Repo", "model": "gpt-4o", "tools": [{"type": "code_interpreter"}], "tool_resources": {"code_interpreter": {"file_ids": ["file_1"]}, "file_search": {"vector_store_ids": ["vs_1"]}}}"#,
+            ),
+            MockResponse::new(
+                200,
+                r#"{"id": "asst_1", "name": "Repo", "model": "gpt-4o", "tools": [{"type": "code_interpreter"}, {"type": "file_search"}]}"#,
+            ),
+        ])
+        .await;
+
+        test_client(&server)
+            .update_assistant("asst_1", &AssistantSettings::default(), "vs_2")
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(requests[1].path, "/assistants/asst_1");
+        assert_eq!(
+            body_json(&requests[1].body),
+            json!({
+                "tools": [{ "type": "code_interpreter" }, { "type": "file_search" }],
+                "tool_resources": {
+                    "code_interpreter": { "file_ids": ["file_1"] },
+                    "file_search": { "vector_store_ids": ["vs_2"] },
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{ChunkSink, RepconError};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Format of an archive bundling all output chunks into a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    Zip,
+    TarGz,
+}
+
+impl BundleFormat {
+    /// Returns the file extension of the format, without the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+        }
+    }
+}
+
+enum Archive {
+    Zip(ZipWriter<NamedTempFile>),
+    /// A tar header contains the size of the entry, so each chunk is spooled
+    /// to a temporary file before it is appended.
+    TarGz {
+        builder: tar::Builder<GzEncoder<NamedTempFile>>,
+        current: Option<(String, File)>,
+    },
+}
+
+/// Writes every chunk as an entry of a single zip or tar.gz archive, as the chunks are produced.
+///
+/// The archive is written to a temporary file next to its path, which
+/// [`BundleSink::finish`] moves into place. If packing fails or the sink is dropped
+/// without being finished, an existing archive at the path is left untouched.
+pub struct BundleSink {
+    path: PathBuf,
+    archive: Archive,
+}
+
+impl BundleSink {
+    /// Creates the temporary file of the archive in the directory of `path`.
+    pub fn create(path: impl Into<PathBuf>, format: BundleFormat) -> Result<Self, RepconError> {
+        let path = path.into();
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file = NamedTempFile::new_in(directory)?;
+        let archive = match format {
+            BundleFormat::Zip => Archive::Zip(ZipWriter::new(file)),
+            BundleFormat::TarGz => Archive::TarGz {
+                builder: tar::Builder::new(GzEncoder::new(file, Compression::default())),
+                current: None,
+            },
+        };
+        Ok(Self { path, archive })
+    }
+
+    /// Returns the path of the archive file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a file that is not an output chunk, e.g. the manifest, to the archive.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), RepconError> {
+        self.begin_chunk(name)?;
+        self.write_all(data)?;
+        self.end_chunk()?;
+        Ok(())
+    }
+
+    /// Writes the end of the archive and moves it to its path, replacing any existing file.
+    /// Returns the path of the archive file.
+    pub fn finish(self) -> Result<PathBuf, RepconError> {
+        let file = match self.archive {
+            Archive::Zip(writer) => writer.finish().map_err(io::Error::from)?,
+            Archive::TarGz { builder, .. } => builder.into_inner()?.finish()?,
+        };
+        // Temporary files are only readable by their owner
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+        }
+        file.persist(&self.path).map_err(|e| e.error)?;
+        Ok(self.path)
+    }
+}
+
+impl ChunkSink for BundleSink {
+    fn begin_chunk(&mut self, name: &str) -> io::Result<()> {
+        match &mut self.archive {
+            Archive::Zip(writer) => {
+                let options =
+                    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+                writer.start_file(name, options)?;
+            }
+            Archive::TarGz { current, .. } => {
+                *current = Some((name.to_string(), tempfile::tempfile()?));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.archive {
+            Archive::Zip(writer) => writer.write_all(data),
+            Archive::TarGz {
+                current: Some((_, file)),
+                ..
+            } => file.write_all(data),
+            Archive::TarGz { current: None, .. } => {
+                Err(io::Error::other("No chunk has been started"))
+            }
+        }
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        if let Archive::TarGz { builder, current } = &mut self.archive {
+            if let Some((name, mut file)) = current.take() {
+                let size = file.seek(SeekFrom::End(0))?;
+                file.rewind()?;
+                let mut header = tar::Header::new_gnu();
+                header.set_size(size);
+                header.set_mode(0o644);
+                header.set_mtime(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |duration| duration.as_secs()),
+                );
+                builder.append_data(&mut header, name, file)?;
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod bundle_tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    fn write_bundle(path: &Path, format: BundleFormat) -> Result<PathBuf, RepconError> {
+        let mut sink = BundleSink::create(path, format)?;
+        for (name, content) in [("output_1.txt", "first\n"), ("output_2.txt", "second\n")] {
+            sink.begin_chunk(name)?;
+            sink.write_all(content.as_bytes())?;
+            assert_eq!(sink.end_chunk()?, None);
+        }
+        sink.finish()
+    }
+
+    #[test]
+    fn test_zip_bundle_contains_chunks() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = write_bundle(&dir.path().join("output.zip"), BundleFormat::Zip)?;
+
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+        assert_eq!(archive.len(), 2);
+        let mut content = String::new();
+        archive
+            .by_name("output_2.txt")?
+            .read_to_string(&mut content)?;
+        assert_eq!(content, "second\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unfinished_bundle_keeps_existing_archive() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let path = dir.path().join("output.zip");
+        std::fs::write(&path, "previous bundle")?;
+
+        let mut sink = BundleSink::create(&path, BundleFormat::Zip)?;
+        sink.begin_chunk("output_1.txt")?;
+        sink.write_all(b"partial")?;
+        drop(sink);
+
+        assert_eq!(std::fs::read_to_string(&path)?, "previous bundle");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tar_gz_bundle_contains_chunks() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let path = write_bundle(&dir.path().join("output.tar.gz"), BundleFormat::TarGz)?;
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+        let mut entries = Vec::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            entries.push((entry.path()?.display().to_string(), content));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                ("output_1.txt".to_string(), "first\n".to_string()),
+                ("output_2.txt".to_string(), "second\n".to_string())
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs::File;
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+
+/// An output chunk held in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Name of the chunk, e.g. `output_1.txt`.
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// Receives the output chunks while the target files are packed.
+///
+/// Chunks are written one after another: `begin_chunk` is called once per chunk,
+/// followed by any number of `write_all` calls and a single `end_chunk`.
+pub trait ChunkSink {
+    /// Starts a new chunk with the given name.
+    fn begin_chunk(&mut self, name: &str) -> io::Result<()>;
+
+    /// Appends data to the current chunk.
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// Finishes the current chunk. Returns the path of the file the chunk was written to,
+    /// if the sink writes chunks to files.
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>>;
+}
+
+/// Writes each chunk to a file named after the chunk in a directory.
+/// The directory must exist.
+#[derive(Debug)]
+pub struct DirectorySink {
+    directory: PathBuf,
+    current: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl DirectorySink {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            current: None,
+        }
+    }
+
+    /// Returns the directory the chunks are written to.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn current(&mut self) -> io::Result<&mut BufWriter<File>> {
+        match &mut self.current {
+            Some((writer, _)) => Ok(writer),
+            None => Err(io::Error::other("No chunk has been started")),
+        }
+    }
+}
+
+impl ChunkSink for DirectorySink {
+    fn begin_chunk(&mut self, name: &str) -> io::Result<()> {
+        let path = self.directory.join(name);
+        let file = File::create(&path)?;
+        self.current = Some((BufWriter::new(file), path));
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.current()?.write_all(data)
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        match self.current.take() {
+            Some((mut writer, path)) => {
+                writer.flush()?;
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Collects the chunks in memory.
+impl ChunkSink for Vec<Chunk> {
+    fn begin_chunk(&mut self, name: &str) -> io::Result<()> {
+        self.push(Chunk {
+            name: name.to_string(),
+            content: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.last_mut() {
+            Some(chunk) => {
+                chunk.content.extend_from_slice(data);
+                Ok(())
+            }
+            None => Err(io::Error::other("No chunk has been started")),
+        }
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+/// Streams the content of every chunk to standard output, one after another.
+#[derive(Debug)]
+pub struct StdoutSink {
+    stdout: StdoutLock<'static>,
+}
+
+impl StdoutSink {
+    pub fn new() -> Self {
+        Self {
+            stdout: io::stdout().lock(),
+        }
+    }
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkSink for StdoutSink {
+    fn begin_chunk(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stdout.write_all(data)
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        self.stdout.flush()?;
+        Ok(None)
+    }
+}
+
+/// Buffers each chunk in memory and passes it to a callback once it is complete,
+/// e.g. to send it over the network without creating temporary files.
+pub struct CallbackSink<F: FnMut(Chunk) -> io::Result<()>> {
+    callback: F,
+    current: Option<Chunk>,
+}
+
+impl<F: FnMut(Chunk) -> io::Result<()>> CallbackSink<F> {
+    pub fn new(callback: F) -> Self {
+        Self {
+            callback,
+            current: None,
+        }
+    }
+}
+
+impl<F: FnMut(Chunk) -> io::Result<()>> ChunkSink for CallbackSink<F> {
+    fn begin_chunk(&mut self, name: &str) -> io::Result<()> {
+        self.current = Some(Chunk {
+            name: name.to_string(),
+            content: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match &mut self.current {
+            Some(chunk) => {
+                chunk.content.extend_from_slice(data);
+                Ok(())
+            }
+            None => Err(io::Error::other("No chunk has been started")),
+        }
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        if let Some(chunk) = self.current.take() {
+            (self.callback)(chunk)?;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod chunk_sink_tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write_chunks(sink: &mut dyn ChunkSink) -> io::Result<Vec<Option<PathBuf>>> {
+        let mut paths = Vec::new();
+        for (name, content) in [("output_1.txt", "first"), ("output_2.txt", "second")] {
+            sink.begin_chunk(name)?;
+            sink.write_all(content.as_bytes())?;
+            sink.write_all(b"\n")?;
+            paths.push(sink.end_chunk()?);
+        }
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_directory_and_memory_sinks() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut sink = DirectorySink::new(dir.path());
+        let paths = write_chunks(&mut sink)?;
+        assert_eq!(
+            paths,
+            vec![
+                Some(dir.path().join("output_1.txt")),
+                Some(dir.path().join("output_2.txt"))
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("output_2.txt"))?,
+            "second\n"
+        );
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        assert_eq!(write_chunks(&mut chunks)?, vec![None, None]);
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    name: "output_1.txt".to_string(),
+                    content: b"first\n".to_vec()
+                },
+                Chunk {
+                    name: "output_2.txt".to_string(),
+                    content: b"second\n".to_vec()
+                }
+            ]
+        );
+        assert!(ChunkSink::write_all(&mut Vec::<Chunk>::new(), b"data").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_callback_sink_receives_complete_chunks() -> io::Result<()> {
+        let mut received = Vec::new();
+        let mut sink = CallbackSink::new(|chunk: Chunk| {
+            received.push((
+                chunk.name,
+                String::from_utf8_lossy(&chunk.content).into_owned(),
+            ));
+            Ok(())
+        });
+        write_chunks(&mut sink)?;
+        drop(sink);
+        assert_eq!(
+            received,
+            vec![
+                ("output_1.txt".to_string(), "first\n".to_string()),
+                ("output_2.txt".to_string(), "second\n".to_string())
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{OutputChunk, RepconError};
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+
+/// Default price of embedding one million tokens, in US dollars (OpenAI `text-embedding-3-small`).
+pub const DEFAULT_EMBEDDING_PRICE: f64 = 0.02;
+
+/// Default price of storing one GB in a vector store for one day, in US dollars.
+pub const DEFAULT_STORAGE_PRICE: f64 = 0.10;
+
+/// Default number of days of vector storage included in the estimate.
+pub const DEFAULT_STORAGE_DAYS: u64 = 30;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Prices used to estimate the cost of indexing the output files.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    /// Price of embedding one million tokens, in US dollars.
+    pub embedding_per_million_tokens: f64,
+    /// Price of storing one GB for one day, in US dollars.
+    pub storage_per_gb_day: f64,
+    /// Number of days of storage to estimate.
+    pub storage_days: u64,
+}
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            embedding_per_million_tokens: DEFAULT_EMBEDDING_PRICE,
+            storage_per_gb_day: DEFAULT_STORAGE_PRICE,
+            storage_days: DEFAULT_STORAGE_DAYS,
+        }
+    }
+}
+
+/// The size and number of tokens of an output chunk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkEstimate {
+    /// Name of the chunk, e.g. `output_1.txt`.
+    pub name: String,
+    pub bytes: u64,
+    pub tokens: u64,
+}
+
+/// The estimated cost of indexing the output files, in US dollars.
+///
+/// Storage is estimated from the size of the output files. Providers may bill the size of
+/// the processed data instead, which includes the embeddings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    pub chunks: Vec<ChunkEstimate>,
+    pub pricing: Pricing,
+    pub total_bytes: u64,
+    pub total_tokens: u64,
+    pub embedding_cost: f64,
+    pub storage_cost_per_day: f64,
+    /// Storage cost for the number of days of the pricing.
+    pub storage_cost: f64,
+    pub total_cost: f64,
+}
+
+/// Counts tokens with the `cl100k_base` encoding used by OpenAI's embedding models.
+pub struct TokenCounter {
+    bpe: CoreBPE,
+}
+impl TokenCounter {
+    /// Loads the encoding, which is bundled with the library.
+    pub fn new() -> Result<Self, RepconError> {
+        let bpe = tiktoken_rs::cl100k_base()
+            .map_err(|e| RepconError::Config(format!("Failed to load the tokenizer: {}", e)))?;
+        Ok(Self { bpe })
+    }
+
+    /// Returns the number of tokens of a text.
+    /// Counting the lines of a text one by one may give a slightly different total.
+    pub fn count(&self, text: &str) -> u64 {
+        self.bpe.encode_ordinary(text).len() as u64
+    }
+}
+
+/// Estimates the cost of indexing output chunks from their sizes and the tokens counted
+/// while packing them, see [`crate::RepconBuilder::count_tokens`].
+/// Chunks whose tokens were not counted are estimated with 0 tokens.
+pub fn estimate_cost(chunks: &[OutputChunk], pricing: Pricing) -> CostEstimate {
+    let chunks: Vec<ChunkEstimate> = chunks
+        .iter()
+        .map(|chunk| ChunkEstimate {
+            name: chunk.name.clone(),
+            bytes: chunk.size,
+            tokens: chunk.tokens.unwrap_or_default(),
+        })
+        .collect();
+
+    let total_bytes: u64 = chunks.iter().map(|chunk| chunk.bytes).sum();
+    let total_tokens: u64 = chunks.iter().map(|chunk| chunk.tokens).sum();
+    let embedding_cost = total_tokens as f64 / 1_000_000.0 * pricing.embedding_per_million_tokens;
+    let storage_cost_per_day = total_bytes as f64 / BYTES_PER_GB * pricing.storage_per_gb_day;
+    let storage_cost = storage_cost_per_day * pricing.storage_days as f64;
+    CostEstimate {
+        chunks,
+        pricing,
+        total_bytes,
+        total_tokens,
+        embedding_cost,
+        storage_cost_per_day,
+        storage_cost,
+        total_cost: embedding_cost + storage_cost,
+    }
+}
+
+#[cfg(test)]
+mod cost_estimate_tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() -> Result<(), RepconError> {
+        let counter = TokenCounter::new()?;
+        assert_eq!(counter.count(""), 0);
+        assert_eq!(counter.count("hello world"), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let chunks = [
+            OutputChunk {
+                name: "output_1.txt".to_string(),
+                size: 11,
+                tokens: Some(2),
+            },
+            OutputChunk {
+                name: "output_2.txt".to_string(),
+                size: 23,
+                tokens: Some(4),
+            },
+        ];
+        let pricing = Pricing {
+            embedding_per_million_tokens: 1_000_000.0,
+            storage_per_gb_day: BYTES_PER_GB,
+            storage_days: 10,
+        };
+        let estimate = estimate_cost(&chunks, pricing);
+        assert_eq!(
+            estimate.chunks[0],
+            ChunkEstimate {
+                name: "output_1.txt".to_string(),
+                bytes: 11,
+                tokens: 2
+            }
+        );
+        assert_eq!(estimate.total_tokens, 6);
+        assert_eq!(estimate.total_bytes, 34);
+        assert_eq!(estimate.embedding_cost, 6.0);
+        assert_eq!(estimate.storage_cost_per_day, 34.0);
+        assert_eq!(estimate.storage_cost, 340.0);
+        assert_eq!(estimate.total_cost, 346.0);
+    }
+}
@@ -0,0 +1,475 @@
+use crate::{detect_language, TargetFile};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path};
+
+/// Extensions tried, in order, when resolving JavaScript and TypeScript imports.
+const SCRIPT_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs", "mts", "cts"];
+
+/// Sorts the target files so that source files are followed by the modules they declare or import.
+///
+/// Rust `mod` and `use crate::` declarations, Python `import` and `from ... import` statements,
+/// and relative JavaScript/TypeScript `import`, `export ... from` and `require` calls are parsed.
+/// Only dependencies that resolve to other target files are taken into account.
+///
+/// Files that are not imported by any other file are visited in path order, each one
+/// followed by its dependencies (depth first), e.g. `lib.rs` followed by its `mod` children.
+/// Files only reachable through import cycles are visited afterwards, also in path order.
+pub fn sort_by_dependencies(files: &mut [TargetFile]) {
+    files.sort_by(|a, b| a.display_path.cmp(&b.display_path));
+
+    let index: HashMap<String, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (normalize_path(&f.display_path), i))
+        .collect();
+    let patterns = ImportPatterns::new();
+    let dependencies: Vec<Vec<usize>> = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| find_dependencies(f, &index, &patterns, i))
+        .collect();
+
+    let mut imported = vec![false; files.len()];
+    for &dependency in dependencies.iter().flatten() {
+        imported[dependency] = true;
+    }
+
+    let mut visited = vec![false; files.len()];
+    let mut order = Vec::with_capacity(files.len());
+    let entry_points = (0..files.len()).filter(|&i| !imported[i]);
+    for start in entry_points.chain(0..files.len()) {
+        // Depth-first traversal, emitting each file before its dependencies
+        let mut stack = vec![start];
+        while let Some(current) = stack.pop() {
+            if visited[current] {
+                continue;
+            }
+            visited[current] = true;
+            order.push(current);
+            stack.extend(dependencies[current].iter().rev().filter(|&&d| !visited[d]));
+        }
+    }
+
+    let sorted: Vec<TargetFile> = order.into_iter().map(|i| files[i].clone()).collect();
+    files.clone_from_slice(&sorted);
+}
+
+/// Regular expressions matching the import statements of each supported language.
+struct ImportPatterns {
+    rust_mod: Regex,
+    rust_use: Regex,
+    python_from: Regex,
+    python_import: Regex,
+    script_import: Regex,
+}
+impl ImportPatterns {
+    fn new() -> Self {
+        Self {
+            rust_mod: Regex::new(
+                r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+([A-Za-z_][A-Za-z0-9_]*)\s*;",
+            )
+            .unwrap(),
+            rust_use: Regex::new(r"\buse\s+crate::").unwrap(),
+            // The names are either on the same line or in parentheses spanning several lines
+            python_from: Regex::new(
+                r"(?m)^[ \t]*from\s+(\.*)([\w.]*)\s+import[ \t]+(?:\(([^)]*)\)|([\w \t,*]+))",
+            )
+            .unwrap(),
+            python_import: Regex::new(r"(?m)^\s*import\s+([\w.]+(?:\s*,\s*[\w.]+)*)").unwrap(),
+            script_import: Regex::new(
+                r#"(?:\b(?:import|export)\s[^'";]*?\bfrom\s*|\bimport\s*\(?\s*|\brequire\s*\(\s*)['"](\.{1,2}/[^'"]*)['"]"#,
+            )
+            .unwrap(),
+        }
+    }
+}
+
+/// Parses the imports of a target file and resolves them to the indices of other target files.
+/// Files that cannot be read as text have no dependencies.
+fn find_dependencies(
+    file: &TargetFile,
+    index: &HashMap<String, usize>,
+    patterns: &ImportPatterns,
+    self_index: usize,
+) -> Vec<usize> {
+    let language = detect_language(&file.display_path);
+    if !matches!(language, "Rust" | "Python" | "JavaScript" | "TypeScript") {
+        return Vec::new();
+    }
+    let Ok(content) = fs::read_to_string(&file.path) else {
+        return Vec::new();
+    };
+    let file_path = normalize_path(&file.display_path);
+
+    let candidates = match language {
+        "Rust" => rust_candidates(&file_path, &content, index, patterns),
+        "Python" => python_candidates(&file_path, &content, patterns),
+        _ => script_candidates(&file_path, &content, patterns),
+    };
+
+    let mut dependencies = Vec::new();
+    for group in candidates {
+        let resolved = group.iter().find_map(|candidate| index.get(candidate));
+        if let Some(&i) = resolved {
+            if i != self_index && !dependencies.contains(&i) {
+                dependencies.push(i);
+            }
+        }
+    }
+    dependencies
+}
+
+/// Returns, for each Rust module declared or used by the file,
+/// the candidate paths of the file defining it.
+fn rust_candidates(
+    file_path: &str,
+    content: &str,
+    index: &HashMap<String, usize>,
+    patterns: &ImportPatterns,
+) -> Vec<Vec<String>> {
+    let directory = parent_of(file_path);
+    let file_name = file_path.rsplit('/').next().unwrap_or_default();
+    let module_directory = if matches!(file_name, "lib.rs" | "main.rs" | "mod.rs") {
+        directory.to_string()
+    } else {
+        join_path(directory, file_name.trim_end_matches(".rs"))
+    };
+
+    let mut candidates: Vec<Vec<String>> = patterns
+        .rust_mod
+        .captures_iter(content)
+        .map(|captures| rust_module_files(&module_directory, &captures[1]))
+        .collect();
+
+    // `use crate::...` refers to modules declared in the crate root
+    if let Some(crate_directory) = find_crate_directory(directory, index) {
+        for path in patterns.rust_use.find_iter(content) {
+            for name in rust_use_modules(&content[path.end()..]) {
+                candidates.push(rust_module_files(&crate_directory, name));
+            }
+        }
+    }
+    candidates
+}
+
+/// Returns the top-level modules of a `use crate::` path, given the text following `crate::`,
+/// e.g. `a` and `d` for `{a::{b, c}, d};`.
+fn rust_use_modules(path: &str) -> Vec<&str> {
+    let items = match path.strip_prefix('{') {
+        Some(group) => {
+            // Split the group at its top-level commas, up to its closing brace
+            let mut items = Vec::new();
+            let mut depth = 0;
+            let mut start = 0;
+            for (i, c) in group.char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => {
+                        items.push(&group[start..i]);
+                        break;
+                    }
+                    '}' => depth -= 1,
+                    ',' if depth == 0 => {
+                        items.push(&group[start..i]);
+                        start = i + 1;
+                    }
+                    _ => {}
+                }
+            }
+            items
+        }
+        None => vec![path],
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            let item = item.trim_start();
+            let end = item
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(item.len());
+            &item[..end]
+        })
+        .filter(|name| !name.is_empty() && *name != "self")
+        .collect()
+}
+
+/// Returns the candidate files of a Rust module declared in the given module directory.
+fn rust_module_files(module_directory: &str, name: &str) -> Vec<String> {
+    vec![
+        join_path(module_directory, &format!("{}.rs", name)),
+        join_path(module_directory, &format!("{}/mod.rs", name)),
+    ]
+}
+
+/// Finds the directory of the crate root (`lib.rs` or `main.rs`) containing the given directory.
+fn find_crate_directory(directory: &str, index: &HashMap<String, usize>) -> Option<String> {
+    let mut current = Some(directory);
+    while let Some(dir) = current {
+        if ["lib.rs", "main.rs"]
+            .iter()
+            .any(|root| index.contains_key(&join_path(dir, root)))
+        {
+            return Some(dir.to_string());
+        }
+        current = (!dir.is_empty()).then(|| parent_of(dir));
+    }
+    None
+}
+
+/// Returns, for each Python module imported by the file,
+/// the candidate paths of the file defining it.
+fn python_candidates(
+    file_path: &str,
+    content: &str,
+    patterns: &ImportPatterns,
+) -> Vec<Vec<String>> {
+    let directory = parent_of(file_path);
+    let mut candidates = Vec::new();
+
+    for captures in patterns.python_from.captures_iter(content) {
+        let dots = captures[1].len();
+        let module = &captures[2];
+        let names: Vec<&str> = captures
+            .get(3)
+            .or_else(|| captures.get(4))
+            .map_or("", |names| names.as_str())
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or_default().split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && *name != "*")
+            .map(|name| name.split_whitespace().next().unwrap_or_default())
+            .collect();
+
+        let bases: Vec<String> = if dots > 0 {
+            let mut base = directory.to_string();
+            for _ in 1..dots {
+                base = parent_of(&base).to_string();
+            }
+            vec![base]
+        } else {
+            ancestors_of(directory)
+        };
+
+        for base in bases {
+            let module_path = join_path(&base, &module.replace('.', "/"));
+            // `from package import module` may import submodules
+            for name in &names {
+                candidates.push(python_module_files(&join_path(&module_path, name)));
+            }
+            if !module.is_empty() {
+                candidates.push(python_module_files(&module_path));
+            }
+        }
+    }
+
+    for captures in patterns.python_import.captures_iter(content) {
+        for module in captures[1].split(',') {
+            let module = module.trim().replace('.', "/");
+            for base in ancestors_of(directory) {
+                candidates.push(python_module_files(&join_path(&base, &module)));
+            }
+        }
+    }
+    candidates
+}
+
+/// Returns the candidate files of a Python module given as a path without extension.
+fn python_module_files(module_path: &str) -> Vec<String> {
+    vec![
+        format!("{}.py", module_path),
+        join_path(module_path, "__init__.py"),
+    ]
+}
+
+/// Returns, for each relative JavaScript/TypeScript import of the file,
+/// the candidate paths of the file it refers to.
+fn script_candidates(
+    file_path: &str,
+    content: &str,
+    patterns: &ImportPatterns,
+) -> Vec<Vec<String>> {
+    let directory = parent_of(file_path);
+    patterns
+        .script_import
+        .captures_iter(content)
+        .map(|captures| {
+            let target = normalize_path(Path::new(&join_path(directory, &captures[1])));
+            let mut files = vec![target.clone()];
+            // TypeScript sources are often imported with the extension of the compiled file
+            if let Some(stem) = target.strip_suffix(".js") {
+                files.extend(["ts", "tsx"].iter().map(|ext| format!("{}.{}", stem, ext)));
+            }
+            files.extend(
+                SCRIPT_EXTENSIONS
+                    .iter()
+                    .map(|ext| format!("{}.{}", target, ext)),
+            );
+            files.extend(
+                SCRIPT_EXTENSIONS
+                    .iter()
+                    .map(|ext| join_path(&target, &format!("index.{}", ext))),
+            );
+            files
+        })
+        .collect()
+}
+
+/// Converts a path to a `/`-separated string, resolving `.` and `..` components.
+fn normalize_path(path: &Path) -> String {
+    let mut components: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                components.pop();
+            }
+            _ => {}
+        }
+    }
+    components.join("/")
+}
+
+/// Returns the parent directory of a `/`-separated path, or an empty string at the top.
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Returns the directory and all its ancestors, from the innermost to the top.
+fn ancestors_of(directory: &str) -> Vec<String> {
+    let mut ancestors = vec![directory.to_string()];
+    let mut current = directory;
+    while !current.is_empty() {
+        current = parent_of(current);
+        ancestors.push(current.to_string());
+    }
+    ancestors
+}
+
+/// Joins two `/`-separated paths, either of which may be empty.
+fn join_path(directory: &str, path: &str) -> String {
+    if directory.is_empty() {
+        path.to_string()
+    } else if path.is_empty() {
+        directory.to_string()
+    } else {
+        format!("{}/{}", directory, path)
+    }
+}
+
+#[cfg(test)]
+mod dependency_ordering_tests {
+    use super::*;
+    use std::io;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn create_target_files(root: &Path, sources: &[(&str, &str)]) -> io::Result<Vec<TargetFile>> {
+        let mut files = Vec::new();
+        for (name, content) in sources {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, content)?;
+            files.push(TargetFile::new(path, Some(root)));
+        }
+        Ok(files)
+    }
+
+    fn display_paths(files: &[TargetFile]) -> Vec<PathBuf> {
+        files.iter().map(|f| f.display_path.clone()).collect()
+    }
+
+    #[test]
+    fn test_sort_rust_modules() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut files = create_target_files(
+            dir.path(),
+            &[
+                ("Cargo.toml", "[package]"),
+                ("src/a.rs", "mod nested;\nuse crate::b::B;"),
+                ("src/a/nested.rs", ""),
+                ("src/b/mod.rs", "pub struct B;"),
+                ("src/lib.rs", "mod b;\npub mod a;"),
+            ],
+        )?;
+
+        sort_by_dependencies(&mut files);
+
+        let expected: Vec<PathBuf> = [
+            "Cargo.toml",
+            "src/lib.rs",
+            "src/b/mod.rs",
+            "src/a.rs",
+            "src/a/nested.rs",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(display_paths(&files), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_python_and_script_imports() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut files = create_target_files(
+            dir.path(),
+            &[
+                ("app/__init__.py", ""),
+                ("app/models.py", ""),
+                ("app/views.py", "from . import models\nimport utils"),
+                ("utils.py", ""),
+                ("web/index.ts", "import { render } from './render.js';"),
+                ("web/render.ts", "const lib = require('./lib');"),
+                ("web/lib/index.js", ""),
+            ],
+        )?;
+
+        sort_by_dependencies(&mut files);
+
+        let expected: Vec<PathBuf> = [
+            "app/__init__.py",
+            "app/views.py",
+            "app/models.py",
+            "utils.py",
+            "web/index.ts",
+            "web/render.ts",
+            "web/lib/index.js",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(display_paths(&files), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rust_use_modules() {
+        assert_eq!(rust_use_modules("{a::{b, c}, d};"), vec!["a", "d"]);
+        assert_eq!(rust_use_modules("{\n    self,\n    e::E,\n};"), vec!["e"]);
+        assert_eq!(rust_use_modules("f::g;\nuse crate::h;"), vec!["f"]);
+    }
+
+    #[test]
+    fn test_python_from_imports() {
+        let patterns = ImportPatterns::new();
+        let candidates: Vec<String> = python_candidates(
+            "app/views.py",
+            "from . import utils\ndef main():\n    pass\nfrom .models import (\n    User,  # the user\n    Group as G,\n)\n",
+            &patterns,
+        )
+        .into_iter()
+        .map(|files| files[0].clone())
+        .collect();
+        assert_eq!(
+            candidates,
+            vec![
+                "app/utils.py",
+                "app/models/User.py",
+                "app/models/Group.py",
+                "app/models.py"
+            ]
+        );
+    }
+}
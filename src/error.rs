@@ -0,0 +1,225 @@
+use crate::openai::{OpenAiError, UploadError};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+/// An error returned by the library.
+///
+/// The library never exits the process; the caller decides how to report each kind of
+/// error, e.g. which exit code to use. It prints only where documented: the
+/// `split_*_into_chunks` functions and [`crate::print_pack_warnings`] print skipped files
+/// and redacted secrets on standard error. Retried requests are reported through
+/// [`crate::OpenAiClient::with_retry_notifier`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RepconError {
+    /// The total size of the target files exceeds the allowed size, in bytes.
+    SizeLimitExceeded { total_size: u64, limit: u64 },
+    /// The maximum output file size is too small to contain a page header and footer.
+    OutputFileTooSmall { max_output_file_size: u64 },
+    /// An ignore pattern, given on the command line or in an ignore file, is invalid.
+    IgnorePattern(ignore::Error),
+    /// A path does not exist or cannot be used.
+    InvalidPath { path: PathBuf, reason: String },
+    /// Data is not valid UTF-8 or cannot be parsed, e.g. a file list or a sync state file.
+    Encoding(String),
+    /// A secret was detected in a target file while secrets are configured to abort packing.
+    SecretDetected { secret: String, location: String },
+    /// An invalid option, pattern or missing credential.
+    Config(String),
+    /// An output file could not be uploaded.
+    Upload(UploadError),
+    /// Some of the output files could not be uploaded.
+    UploadsFailed { failed: usize, total: usize },
+    /// Some of the files attached to a vector store could not be indexed.
+    IndexingFailed {
+        vector_store_id: String,
+        failed: usize,
+        total: usize,
+    },
+    /// Some of the uploaded files could not be deleted.
+    DeletionsFailed { failed: usize, total: usize },
+    /// A request to the OpenAI API failed.
+    Api(OpenAiError),
+    /// A request to another upload destination failed.
+    Request {
+        action: String,
+        status: Option<u16>,
+        code: Option<String>,
+        message: String,
+    },
+    /// The operation is not supported by the upload destination.
+    Unsupported(String),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+}
+impl RepconError {
+    /// Returns the HTTP status of a failed request, if the server responded.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Upload(e) => e.status,
+            Self::Api(e) => e.status,
+            Self::Request { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RepconError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SizeLimitExceeded { total_size, limit } => write!(
+                f,
+                "The total size of the files ({}) exceeds the allowed limit of {} bytes.",
+                total_size, limit
+            ),
+            Self::OutputFileTooSmall {
+                max_output_file_size,
+            } => write!(
+                f,
+                "The maximum file size ({}) is too small to contain the page header and footer.",
+                max_output_file_size
+            ),
+            Self::IgnorePattern(e) => write!(f, "Invalid ignore pattern: {}", e),
+            Self::InvalidPath { path, reason } => write!(f, "{}: {}", reason, path.display()),
+            Self::Encoding(message) | Self::Config(message) | Self::Unsupported(message) => {
+                write!(f, "{}", message)
+            }
+            Self::SecretDetected { secret, location } => {
+                write!(f, "Secret ({}) detected in {}", secret, location)
+            }
+            Self::Upload(e) => write!(f, "{}", e),
+            Self::UploadsFailed { failed, total } => {
+                write!(f, "{} of {} uploads failed", failed, total)
+            }
+            Self::IndexingFailed {
+                vector_store_id,
+                failed,
+                total,
+            } => write!(
+                f,
+                "{} of {} files failed to index in vector store {}",
+                failed, total, vector_store_id
+            ),
+            Self::DeletionsFailed { failed, total } => {
+                write!(f, "{} of {} deletions failed", failed, total)
+            }
+            Self::Api(e) => write!(f, "{}", e),
+            Self::Request {
+                action,
+                status,
+                code,
+                message,
+            } => {
+                write!(f, "Failed to {}", action)?;
+                if let Some(status) = status {
+                    write!(f, " (HTTP {}", status)?;
+                    if let Some(code) = code {
+                        write!(f, ", {}", code)?;
+                    }
+                    write!(f, ")")?;
+                }
+                write!(f, ": {}", message)
+            }
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for RepconError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::IgnorePattern(e) => Some(e),
+            Self::Upload(e) => Some(e),
+            Self::Api(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RepconError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ignore::Error> for RepconError {
+    fn from(e: ignore::Error) -> Self {
+        Self::IgnorePattern(e)
+    }
+}
+
+impl From<UploadError> for RepconError {
+    fn from(e: UploadError) -> Self {
+        Self::Upload(e)
+    }
+}
+
+impl From<OpenAiError> for RepconError {
+    fn from(e: OpenAiError) -> Self {
+        Self::Api(e)
+    }
+}
+
+/// Allows the library to be used from code that works with `io::Result`.
+impl From<RepconError> for io::Error {
+    fn from(e: RepconError) -> Self {
+        if let RepconError::Io(e) = e {
+            return e;
+        }
+        let kind = match &e {
+            RepconError::InvalidPath { .. } => ErrorKind::NotFound,
+            RepconError::Config(_) | RepconError::IgnorePattern(_) => ErrorKind::InvalidInput,
+            RepconError::Encoding(_) | RepconError::SecretDetected { .. } => ErrorKind::InvalidData,
+            RepconError::Unsupported(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn test_error_messages_and_status() {
+        let error = RepconError::SizeLimitExceeded {
+            total_size: 2048,
+            limit: 1024,
+        };
+        assert_eq!(
+            error.to_string(),
+            "The total size of the files (2048) exceeds the allowed limit of 1024 bytes."
+        );
+        assert_eq!(error.status(), None);
+
+        let error = RepconError::Request {
+            action: "delete packs/output_1.txt".to_string(),
+            status: Some(403),
+            code: Some("AccessDenied".to_string()),
+            message: "Access Denied".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Failed to delete packs/output_1.txt (HTTP 403, AccessDenied): Access Denied"
+        );
+        assert_eq!(error.status(), Some(403));
+    }
+
+    #[test]
+    fn test_conversion_to_io_error() {
+        let error = io::Error::from(RepconError::Io(io::Error::new(
+            ErrorKind::PermissionDenied,
+            "denied",
+        )));
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+
+        let error = io::Error::from(RepconError::Config("Missing API key".to_string()));
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "Missing API key");
+        assert!(error.into_inner().unwrap().is::<RepconError>());
+    }
+}
@@ -1,8 +1,81 @@
+use crate::{PageFormat, RepconError};
 use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use std::collections::HashMap;
 use std::fs;
-use std::io;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
+/// Name of the ignore file that each input root may contain.
+pub const REPCONIGNORE_FILE_NAME: &str = ".repconignore";
+
+/// A root directory to collect target files from.
+///
+/// The optional alias is prepended to the relative paths of the root's files in the output,
+/// so that files from several repositories can be told apart in a single pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputRoot {
+    pub path: PathBuf,
+    pub alias: Option<String>,
+}
+impl InputRoot {
+    /// Constructs a new `InputRoot` from a directory path and an optional alias.
+    pub fn new(path: impl Into<PathBuf>, alias: Option<String>) -> Self {
+        Self {
+            path: path.into(),
+            alias,
+        }
+    }
+
+    /// Parses a root given on the command line, either as `PATH` or as `ALIAS=PATH`.
+    ///
+    /// The part before `=` is only treated as an alias if it is non-empty and contains
+    /// no path separators, so paths that happen to contain `=` are kept intact.
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once('=') {
+            Some((alias, path))
+                if !alias.is_empty() && !path.is_empty() && !alias.contains(['/', '\\']) =>
+            {
+                Self::new(path, Some(alias.to_string()))
+            }
+            _ => Self::new(spec, None),
+        }
+    }
+
+    /// Returns the path of a file under this root as it is shown in the page headers:
+    /// relative to the root and prefixed with the alias, if any.
+    pub fn display_path(&self, file_path: &Path) -> PathBuf {
+        self.display_path_from(&self.path, file_path)
+    }
+
+    /// Same as `display_path`, but with the root directory given explicitly,
+    /// e.g. in its canonical form.
+    fn display_path_from(&self, root_path: &Path, file_path: &Path) -> PathBuf {
+        let relative_path = PageFormat::to_relative_path(root_path, file_path);
+        match &self.alias {
+            Some(alias) => Path::new(alias).join(relative_path),
+            None => relative_path,
+        }
+    }
+}
+
+/// A file selected for packing, together with the path shown for it in the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetFile {
+    pub path: PathBuf,
+    pub display_path: PathBuf,
+}
+impl TargetFile {
+    /// Constructs a new `TargetFile`.
+    /// If a root is given, the display path is made relative to it.
+    pub fn new(path: PathBuf, root: Option<&Path>) -> Self {
+        let display_path = match root {
+            Some(root) => PageFormat::to_relative_path(root, &path),
+            None => path.clone(),
+        };
+        Self { path, display_path }
+    }
+}
+
 /// Collects a list of target files within a directory, applying custom ignore patterns.
 /// Respects ignore rules from `.repconignore` file if provided.
 /// Returns a vector of relative paths to the files that are not ignored.
@@ -10,54 +83,186 @@ pub fn collect_target_files(
     dir: &Path,
     ignore_patterns: &[String],
     reconignore_path: Option<&String>,
-) -> io::Result<Vec<PathBuf>> {
+) -> Result<Vec<PathBuf>, RepconError> {
+    let ignore_files: Vec<PathBuf> = reconignore_path.iter().map(PathBuf::from).collect();
+    walk_target_files(dir, ignore_patterns, &ignore_files)
+}
+
+/// Collects the target files of several roots into a single list.
+///
+/// Each root applies its own `.gitignore` rules and the `.repconignore` file in its directory,
+/// in addition to the shared ignore patterns and `.repconignore` file given here.
+/// Files are returned root by root, in the order the roots are given.
+///
+/// Returns `RepconError::Config` if files of two roots would be shown with the same path,
+/// e.g. because the roots have no alias or the same alias.
+pub fn collect_target_files_from_roots(
+    roots: &[InputRoot],
+    ignore_patterns: &[String],
+    reconignore_path: Option<&String>,
+) -> Result<Vec<TargetFile>, RepconError> {
+    let mut files: Vec<TargetFile> = Vec::new();
+    let mut display_paths: HashMap<PathBuf, usize> = HashMap::new();
+    for root in roots {
+        if !root.path.is_dir() {
+            return Err(RepconError::InvalidPath {
+                path: root.path.clone(),
+                reason: "Root directory not found".to_string(),
+            });
+        }
+
+        let ignore_files = root_ignore_files(root, reconignore_path);
+        for path in walk_target_files(&root.path, ignore_patterns, &ignore_files)? {
+            let display_path = root.display_path(&path);
+            if let Some(&index) = display_paths.get(&display_path) {
+                return Err(RepconError::Config(format!(
+                    "{} and {} are both shown as {} in the output. \
+                     Give the roots distinct aliases with ALIAS=PATH.",
+                    files[index].path.display(),
+                    path.display(),
+                    display_path.display()
+                )));
+            }
+            display_paths.insert(display_path.clone(), files.len());
+            files.push(TargetFile { path, display_path });
+        }
+    }
+    Ok(files)
+}
+
+/// Reads an explicit list of target files, e.g. the output of `rg -l` or `find -print0`.
+/// Entries are NUL-delimited if the input contains a NUL byte, and newline-delimited otherwise.
+/// Empty entries are skipped.
+pub fn read_file_list<R: Read>(mut reader: R) -> Result<Vec<PathBuf>, RepconError> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    let content = String::from_utf8(content).map_err(|_| {
+        RepconError::Encoding("File list contains invalid UTF-8 characters".to_string())
+    })?;
+
+    let entries: Vec<&str> = if content.contains('\0') {
+        content.split('\0').collect()
+    } else {
+        content.lines().collect()
+    };
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Converts an explicit list of files into target files without walking any directory.
+/// The display path of each file is computed relative to the first root that contains it,
+/// and prefixed with that root's alias. Files outside every root keep their path as given.
+pub fn target_files_from_list(paths: Vec<PathBuf>, roots: &[InputRoot]) -> Vec<TargetFile> {
+    let canonical_roots: Vec<Option<PathBuf>> = roots
+        .iter()
+        .map(|root| fs::canonicalize(&root.path).ok())
+        .collect();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let display_path = fs::canonicalize(&path).ok().and_then(|canonical_path| {
+                roots
+                    .iter()
+                    .zip(&canonical_roots)
+                    .find_map(|(root, canonical_root)| {
+                        let canonical_root = canonical_root.as_ref()?;
+                        canonical_path
+                            .starts_with(canonical_root)
+                            .then(|| root.display_path_from(canonical_root, &canonical_path))
+                    })
+            });
+            TargetFile {
+                display_path: display_path.unwrap_or_else(|| path.clone()),
+                path,
+            }
+        })
+        .collect()
+}
+
+/// Returns the ignore files applied to a root: the shared `.repconignore` file, if any,
+/// and the `.repconignore` file in the root directory.
+pub(crate) fn root_ignore_files(
+    root: &InputRoot,
+    reconignore_path: Option<&String>,
+) -> Vec<PathBuf> {
+    let mut ignore_files: Vec<PathBuf> = reconignore_path.iter().map(PathBuf::from).collect();
+    let root_ignore_file = root.path.join(REPCONIGNORE_FILE_NAME);
+    if !ignore_files
+        .iter()
+        .any(|f| is_same_file(f, &root_ignore_file))
+    {
+        ignore_files.push(root_ignore_file);
+    }
+    ignore_files
+}
+
+/// Walks a directory and returns the files that are not ignored
+/// by `.gitignore` rules, the given patterns or the given ignore files.
+/// Ignore files that do not exist are skipped.
+fn walk_target_files(
+    dir: &Path,
+    ignore_patterns: &[String],
+    ignore_files: &[PathBuf],
+) -> Result<Vec<PathBuf>, RepconError> {
     let mut files = Vec::new();
+    let walker = target_walk_builder(dir, ignore_patterns, ignore_files)?.build();
+
+    // Collect files that are not ignored
+    for entry in walker.flatten() {
+        if entry.file_type().is_some_and(|ft| ft.is_file()) {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Sets up the directory walker that yields the files and directories that are not ignored.
+pub(crate) fn target_walk_builder(
+    dir: &Path,
+    ignore_patterns: &[String],
+    ignore_files: &[PathBuf],
+) -> Result<WalkBuilder, RepconError> {
     let mut override_builder = OverrideBuilder::new(dir);
 
     // Add ignore patterns from args
     for rule in ignore_patterns {
-        override_builder
-            .add(format!("!{}", rule).as_str())
-            .map_err(convert_ignore_error)?;
+        override_builder.add(format!("!{}", rule).as_str())?;
     }
 
-    // If a .repconignore file is provided, add its ignore patterns
-    if let Some(ignore_file) = reconignore_path {
-        if Path::new(ignore_file).exists() {
+    // Add the ignore patterns of each .repconignore file that exists
+    for ignore_file in ignore_files {
+        if ignore_file.exists() {
             let ignore_content = fs::read_to_string(ignore_file)?;
             for line in ignore_content.lines() {
                 if !line.trim().is_empty() && !line.starts_with('#') {
-                    override_builder
-                        .add(format!("!{}", line).as_str())
-                        .map_err(convert_ignore_error)?;
+                    override_builder.add(format!("!{}", line).as_str())?;
                 }
             }
         }
     }
 
-    let overrides = override_builder.build().map_err(convert_ignore_error)?;
-    let walker = WalkBuilder::new(dir).overrides(overrides).build();
-
-    // Collect files that are not ignored
-    for result in walker {
-        if let Ok(entry) = result {
-            if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                files.push(entry.into_path());
-            }
-        }
-    }
-
-    Ok(files)
+    let overrides = override_builder.build()?;
+    let mut walk_builder = WalkBuilder::new(dir);
+    walk_builder.overrides(overrides);
+    Ok(walk_builder)
 }
 
-/// Converts ignore library errors to standard IO errors.
-fn convert_ignore_error(e: ignore::Error) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, e)
+/// Returns true if both paths exist and point to the same file.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
 /// Calculates the total size of a list of files, given their relative paths and root directory.
 /// Returns the total size in bytes.
-pub fn get_dir_size(root_path: &Path, files: &[PathBuf]) -> io::Result<u64> {
+pub fn get_dir_size(root_path: &Path, files: &[PathBuf]) -> Result<u64, RepconError> {
     let total_size: u64 = files
         .iter()
         .filter_map(|f| fs::metadata(root_path.join(f)).ok())
@@ -67,15 +272,25 @@ pub fn get_dir_size(root_path: &Path, files: &[PathBuf]) -> io::Result<u64> {
     Ok(total_size)
 }
 
+/// Calculates the total size of the collected target files in bytes.
+pub fn get_target_files_size(files: &[TargetFile]) -> Result<u64, RepconError> {
+    let total_size: u64 = files
+        .iter()
+        .filter_map(|f| fs::metadata(&f.path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+
+    Ok(total_size)
+}
+
 /// Checks if the total size of files exceeds a specified limit.
-/// If the limit is exceeded, prints an error message and exits the process.
-pub fn check_size_limits(total_size: u64, total_allowed_size: u64) -> io::Result<()> {
+/// Returns `RepconError::SizeLimitExceeded` if the limit is exceeded.
+pub fn check_size_limits(total_size: u64, total_allowed_size: u64) -> Result<(), RepconError> {
     if total_size > total_allowed_size {
-        eprintln!(
-            "Error: The total size of the files ({}) exceeds the allowed limit of {} bytes.",
-            total_size, total_allowed_size
-        );
-        std::process::exit(1);
+        return Err(RepconError::SizeLimitExceeded {
+            total_size,
+            limit: total_allowed_size,
+        });
     }
     Ok(())
 }
@@ -99,10 +314,12 @@ pub fn format_file_size(size: u64) -> String {
 }
 
 #[cfg(test)]
+// The older test helpers predate these lints
+#[allow(clippy::needless_as_bytes, clippy::type_complexity)]
 mod tests {
     use super::*;
     use std::fs::{self, File};
-    use std::io::Write;
+    use std::io::{self, Write};
     use tempfile::tempdir;
 
     struct FileInfo {
@@ -272,4 +489,129 @@ mod tests {
         dir.close()?;
         Ok(())
     }
+
+    #[test]
+    fn test_input_root_parse() {
+        assert_eq!(InputRoot::parse("."), InputRoot::new(".", None));
+        assert_eq!(
+            InputRoot::parse("backend=../backend"),
+            InputRoot::new("../backend", Some("backend".to_string()))
+        );
+        assert_eq!(
+            InputRoot::parse("./dir/a=b"),
+            InputRoot::new("./dir/a=b", None)
+        );
+    }
+
+    #[test]
+    fn test_collect_from_multiple_roots() -> io::Result<()> {
+        let (dir1, path1, _, _, _) = setup_test_environment(vec![], None)?;
+        let (dir2, path2, _, _, _) = setup_test_environment(vec![], Some("test_file2*"))?;
+        let roots = vec![
+            InputRoot::new(&path1, Some("backend".to_string())),
+            InputRoot::new(&path2, None),
+        ];
+        let mut files = collect_target_files_from_roots(&roots, &[], None)?;
+        files.sort_by(|a, b| a.display_path.cmp(&b.display_path));
+        let display_paths: Vec<PathBuf> = files.into_iter().map(|f| f.display_path).collect();
+
+        // The second root applies its own .repconignore file
+        assert_eq!(
+            display_paths,
+            vec![
+                PathBuf::from("backend/test_file.txt"),
+                PathBuf::from("backend/test_file2.txt"),
+                PathBuf::from("test_file.txt"),
+            ]
+        );
+        dir1.close()?;
+        dir2.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_colliding_display_paths_are_rejected() -> io::Result<()> {
+        let (dir1, path1, _, _, _) = setup_test_environment(vec![], None)?;
+        let (dir2, path2, _, _, _) = setup_test_environment(vec![], None)?;
+        for alias in [None, Some("app".to_string())] {
+            let roots = vec![
+                InputRoot::new(&path1, alias.clone()),
+                InputRoot::new(&path2, alias),
+            ];
+            let result = collect_target_files_from_roots(&roots, &[], None);
+            assert!(matches!(result, Err(RepconError::Config(_))));
+        }
+
+        let roots = vec![
+            InputRoot::new(&path1, Some("a".to_string())),
+            InputRoot::new(&path2, Some("b".to_string())),
+        ];
+        assert_eq!(collect_target_files_from_roots(&roots, &[], None)?.len(), 4);
+        dir1.close()?;
+        dir2.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_file_list() -> io::Result<()> {
+        let newline_list = read_file_list("src/main.rs\r\n\nsrc/lib.rs\n".as_bytes())?;
+        assert_eq!(
+            newline_list,
+            vec![PathBuf::from("src/main.rs"), PathBuf::from("src/lib.rs")]
+        );
+
+        let nul_list = read_file_list("a file.txt\0with\nnewline.txt\0".as_bytes())?;
+        assert_eq!(
+            nul_list,
+            vec![
+                PathBuf::from("a file.txt"),
+                PathBuf::from("with\nnewline.txt")
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_files_from_list() -> io::Result<()> {
+        let (dir, path_to_repo, _, _, _) = setup_test_environment(vec![], None)?;
+        let roots = vec![InputRoot::new(&path_to_repo, Some("repo".to_string()))];
+        let paths = vec![
+            path_to_repo.join("test_file2.txt"),
+            PathBuf::from("missing.txt"),
+        ];
+
+        let files = target_files_from_list(paths, &roots);
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, path_to_repo.join("test_file2.txt"));
+        assert_eq!(files[0].display_path, PathBuf::from("repo/test_file2.txt"));
+        assert_eq!(files[1].display_path, PathBuf::from("missing.txt"));
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_are_typed() {
+        assert!(check_size_limits(1024, 1024).is_ok());
+        assert!(matches!(
+            check_size_limits(1025, 1024),
+            Err(RepconError::SizeLimitExceeded {
+                total_size: 1025,
+                limit: 1024
+            })
+        ));
+        assert!(matches!(
+            read_file_list(&b"\xff\xfe"[..]),
+            Err(RepconError::Encoding(_))
+        ));
+        let roots = vec![InputRoot::new("missing-root", None)];
+        assert!(matches!(
+            collect_target_files_from_roots(&roots, &[], None),
+            Err(RepconError::InvalidPath { .. })
+        ));
+        assert!(matches!(
+            collect_target_files(Path::new("."), &["[".to_string()], None),
+            Err(RepconError::IgnorePattern(_))
+        ));
+    }
 }
@@ -0,0 +1,370 @@
+use crate::{detect_language, sort_by_dependencies, RepconError, TargetFile};
+use globset::{Glob, GlobMatcher};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Patterns used by `FileOrder::Priority` when no custom priority list is given.
+/// READMEs come first, followed by the manifests of common package managers.
+pub const DEFAULT_PRIORITY_PATTERNS: &[&str] = &[
+    "README*",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "requirements.txt",
+    "go.mod",
+    "pom.xml",
+    "build.gradle*",
+    "Gemfile",
+    "composer.json",
+];
+
+/// The order in which target files are written to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileOrder {
+    /// Sort by path.
+    #[default]
+    Path,
+    /// Sort by path, listing the contents of subdirectories before the files of a directory.
+    DirFirst,
+    /// Sort by file size, smallest first.
+    Size,
+    /// Group files by language, then sort by path.
+    Language,
+    /// Sort by the date of the last git commit touching each file, most recent first.
+    /// Files without git history come last, sorted by path, so outside a git repository
+    /// or without git installed this is the same as `Path`.
+    Git,
+    /// Sort files matching the priority patterns first, in the order of the patterns,
+    /// followed by the remaining files sorted by path.
+    Priority,
+    /// Place Rust, Python and JavaScript/TypeScript source files right before
+    /// the modules they declare or import.
+    Dependency,
+}
+
+/// Sorts the target files in the given order.
+///
+/// The display path is used as a tie-breaker for every order,
+/// so the result is deterministic across platforms and filesystems.
+/// `priority_patterns` is only used by `FileOrder::Priority`; if it is empty,
+/// `DEFAULT_PRIORITY_PATTERNS` is used. Patterns without a `/` match file names,
+/// other patterns match the whole display path.
+pub fn sort_target_files(
+    files: &mut [TargetFile],
+    order: FileOrder,
+    priority_patterns: &[String],
+) -> Result<(), RepconError> {
+    match order {
+        FileOrder::Path => files.sort_by(|a, b| a.display_path.cmp(&b.display_path)),
+        FileOrder::DirFirst => {
+            files.sort_by(|a, b| compare_directory_first(&a.display_path, &b.display_path))
+        }
+        FileOrder::Size => files.sort_by_cached_key(|f| {
+            let size = fs::metadata(&f.path).map_or(0, |metadata| metadata.len());
+            (size, f.display_path.clone())
+        }),
+        FileOrder::Language => {
+            files.sort_by_cached_key(|f| (detect_language(&f.display_path), f.display_path.clone()))
+        }
+        FileOrder::Git => {
+            let commit_times = git_commit_times(files);
+            files.sort_by_cached_key(|f| {
+                let commit_time = commit_times.get(&f.path).copied();
+                (
+                    commit_time.is_none(),
+                    std::cmp::Reverse(commit_time),
+                    f.display_path.clone(),
+                )
+            })
+        }
+        FileOrder::Priority => {
+            let matchers = build_priority_matchers(priority_patterns)?;
+            files.sort_by_cached_key(|f| {
+                let priority = matchers
+                    .iter()
+                    .position(|matcher| matcher.is_match(&f.display_path))
+                    .unwrap_or(matchers.len());
+                (priority, f.display_path.clone())
+            })
+        }
+        FileOrder::Dependency => sort_by_dependencies(files),
+    }
+    Ok(())
+}
+
+/// A glob pattern of the priority list.
+struct PriorityMatcher {
+    matcher: GlobMatcher,
+    match_file_name: bool,
+}
+impl PriorityMatcher {
+    fn is_match(&self, path: &Path) -> bool {
+        if self.match_file_name {
+            path.file_name()
+                .is_some_and(|name| self.matcher.is_match(name))
+        } else {
+            self.matcher.is_match(path)
+        }
+    }
+}
+
+/// Compiles the priority patterns, falling back to the default patterns if none are given.
+fn build_priority_matchers(
+    priority_patterns: &[String],
+) -> Result<Vec<PriorityMatcher>, RepconError> {
+    let patterns: Vec<&str> = if priority_patterns.is_empty() {
+        DEFAULT_PRIORITY_PATTERNS.to_vec()
+    } else {
+        priority_patterns.iter().map(String::as_str).collect()
+    };
+
+    patterns
+        .into_iter()
+        .map(|pattern| {
+            let glob = Glob::new(pattern).map_err(|e| {
+                RepconError::Config(format!("Invalid priority pattern {:?}: {}", pattern, e))
+            })?;
+            Ok(PriorityMatcher {
+                matcher: glob.compile_matcher(),
+                match_file_name: !pattern.contains('/'),
+            })
+        })
+        .collect()
+}
+
+/// Compares two paths component by component,
+/// placing entries that are directories before entries that are files.
+fn compare_directory_first(a: &Path, b: &Path) -> Ordering {
+    let a_components: Vec<Component> = a.components().collect();
+    let b_components: Vec<Component> = b.components().collect();
+
+    for (i, (a_component, b_component)) in a_components.iter().zip(&b_components).enumerate() {
+        if a_component != b_component {
+            let a_is_directory = i + 1 < a_components.len();
+            let b_is_directory = i + 1 < b_components.len();
+            return b_is_directory
+                .cmp(&a_is_directory)
+                .then_with(|| a_component.cmp(b_component));
+        }
+    }
+    a_components.len().cmp(&b_components.len())
+}
+
+/// Looks up the time of the last commit touching each target file.
+/// Runs `git log` once per repository containing target files.
+/// Returns a map from the target file path to a Unix timestamp;
+/// files that are not tracked by git are missing from the map.
+fn git_commit_times(files: &[TargetFile]) -> HashMap<PathBuf, u64> {
+    let mut toplevels: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    let mut repository_times: HashMap<PathBuf, HashMap<PathBuf, u64>> = HashMap::new();
+    let mut commit_times = HashMap::new();
+
+    for file in files {
+        let Ok(path) = fs::canonicalize(&file.path) else {
+            continue;
+        };
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+        let toplevel = toplevels
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| git_toplevel(parent));
+        let Some(toplevel) = toplevel else {
+            continue;
+        };
+        let times = repository_times
+            .entry(toplevel.clone())
+            .or_insert_with(|| git_log_times(toplevel));
+        if let Some(time) = path
+            .strip_prefix(&*toplevel)
+            .ok()
+            .and_then(|relative_path| times.get(relative_path))
+        {
+            commit_times.insert(file.path.clone(), *time);
+        }
+    }
+    commit_times
+}
+
+/// Returns the root directory of the git repository containing `dir`, if any.
+fn git_toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let toplevel = String::from_utf8(output.stdout).ok()?;
+    fs::canonicalize(toplevel.trim_end()).ok()
+}
+
+/// Returns the time of the last commit touching each file of a git repository,
+/// keyed by the path relative to the repository root.
+fn git_log_times(toplevel: &Path) -> HashMap<PathBuf, u64> {
+    let mut times = HashMap::new();
+    let Ok(output) = Command::new("git")
+        .arg("-C")
+        .arg(toplevel)
+        .args([
+            "-c",
+            "core.quotepath=off",
+            "log",
+            "--format=%x00%ct",
+            "--name-only",
+        ])
+        .output()
+    else {
+        return times;
+    };
+    if !output.status.success() {
+        return times;
+    }
+
+    // Commits are listed from newest to oldest, so the first time seen for a file is its latest
+    let mut current_time = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(timestamp) = line.strip_prefix('\0') {
+            current_time = timestamp.trim().parse::<u64>().ok();
+        } else if let (false, Some(time)) = (line.is_empty(), current_time) {
+            times.entry(PathBuf::from(line)).or_insert(time);
+        }
+    }
+    times
+}
+
+#[cfg(test)]
+mod file_ordering_tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::{self, Write};
+    use tempfile::tempdir;
+
+    fn display_paths(files: &[TargetFile]) -> Vec<&str> {
+        files
+            .iter()
+            .map(|f| f.display_path.to_str().unwrap())
+            .collect()
+    }
+
+    fn target_files(paths: &[&str]) -> Vec<TargetFile> {
+        paths
+            .iter()
+            .map(|path| TargetFile::new(PathBuf::from(path), None))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_by_path_and_directory_first() -> io::Result<()> {
+        let paths = ["src/main.rs", "README.md", "src/a/mod.rs", "Cargo.toml"];
+
+        let mut files = target_files(&paths);
+        sort_target_files(&mut files, FileOrder::Path, &[])?;
+        assert_eq!(
+            display_paths(&files),
+            ["Cargo.toml", "README.md", "src/a/mod.rs", "src/main.rs"]
+        );
+
+        let mut files = target_files(&paths);
+        sort_target_files(&mut files, FileOrder::DirFirst, &[])?;
+        assert_eq!(
+            display_paths(&files),
+            ["src/a/mod.rs", "src/main.rs", "Cargo.toml", "README.md"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_language_and_priority() -> io::Result<()> {
+        let paths = ["src/lib.rs", "docs/guide.md", "Cargo.toml", "README.md"];
+
+        let mut files = target_files(&paths);
+        sort_target_files(&mut files, FileOrder::Language, &[])?;
+        assert_eq!(
+            display_paths(&files),
+            ["README.md", "docs/guide.md", "src/lib.rs", "Cargo.toml"]
+        );
+
+        let mut files = target_files(&paths);
+        sort_target_files(&mut files, FileOrder::Priority, &[])?;
+        assert_eq!(
+            display_paths(&files),
+            ["README.md", "Cargo.toml", "docs/guide.md", "src/lib.rs"]
+        );
+
+        let mut files = target_files(&paths);
+        sort_target_files(&mut files, FileOrder::Priority, &["docs/*".to_string()])?;
+        assert_eq!(
+            display_paths(&files),
+            ["docs/guide.md", "Cargo.toml", "README.md", "src/lib.rs"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_size() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut files = Vec::new();
+        for (name, content) in [
+            ("a.txt", "large file"),
+            ("b.txt", "small"),
+            ("c.txt", "small"),
+        ] {
+            let path = dir.path().join(name);
+            File::create(&path)?.write_all(content.as_bytes())?;
+            files.push(TargetFile::new(path, Some(dir.path())));
+        }
+
+        sort_target_files(&mut files, FileOrder::Size, &[])?;
+        assert_eq!(display_paths(&files), ["b.txt", "c.txt", "a.txt"]);
+        Ok(())
+    }
+
+    /// Runs git in `dir` with a fixed identity and commit date.
+    fn git(dir: &Path, args: &[&str], date: &str) -> io::Result<()> {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()?;
+        assert!(status.success(), "git {:?} failed", args);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_git_commit_time() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut files = Vec::new();
+        for name in ["c.txt", "b.txt", "a.txt"] {
+            let path = dir.path().join(name);
+            File::create(&path)?.write_all(name.as_bytes())?;
+            files.push(TargetFile::new(path, Some(dir.path())));
+        }
+
+        // Outside a git repository, files are sorted by path
+        sort_target_files(&mut files, FileOrder::Git, &[])?;
+        assert_eq!(display_paths(&files), ["a.txt", "b.txt", "c.txt"]);
+
+        // b.txt is changed last, and c.txt is not tracked
+        let date = "2024-01-01T00:00:00Z";
+        git(dir.path(), &["init", "-q"], date)?;
+        git(dir.path(), &["add", "a.txt", "b.txt"], date)?;
+        git(dir.path(), &["commit", "-q", "-m", "first"], date)?;
+        File::create(dir.path().join("b.txt"))?.write_all(b"changed")?;
+        let date = "2024-01-02T00:00:00Z";
+        git(dir.path(), &["commit", "-q", "-a", "-m", "second"], date)?;
+
+        sort_target_files(&mut files, FileOrder::Git, &[])?;
+        assert_eq!(display_paths(&files), ["b.txt", "a.txt", "c.txt"]);
+        Ok(())
+    }
+}
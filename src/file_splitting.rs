@@ -1,18 +1,26 @@
-use crate::PageFormat;
+use crate::{
+    ChunkSink, DirectorySink, PageFormat, RepconError, SecretRedactor, TargetFile, TokenCounter,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 
-/// Creates a new output file in the specified directory with a given name and counter.
-/// Returns a tuple containing the created File and its PathBuf.
-fn create_new_output_file(
-    output_directory: &Path,
-    file_counter: u64,
-    output_name: &str,
-) -> io::Result<(File, PathBuf)> {
-    let file_path = output_directory.join(format!("{}_{}.txt", output_name, file_counter));
-    let file = File::create(&file_path)?;
-    Ok((file, file_path))
+/// Returns the name of the output file with the given number, i.e. `{output_name}_{file_no}.txt`.
+pub fn output_file_name(output_name: &str, file_no: u64) -> String {
+    format!("{}_{}.txt", output_name, file_no)
+}
+
+/// Returns true if a file name has the form of the output files named after `output_name`,
+/// i.e. `{output_name}_{file_no}.txt`. Leading directories are ignored.
+pub fn is_output_file_name(file_name: &str, output_name: &str) -> bool {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    file_name
+        .strip_prefix(output_name)
+        .and_then(|rest| rest.strip_prefix('_'))
+        .and_then(|rest| rest.strip_suffix(".txt"))
+        .is_some_and(|file_no| !file_no.is_empty() && file_no.bytes().all(|b| b.is_ascii_digit()))
 }
 
 /// Checks if the size of the page header and footer exceeds the maximum allowed file size.
@@ -20,37 +28,174 @@ fn create_new_output_file(
 fn check_max_output_file_size(
     page_format: &PageFormat,
     max_output_file_size: u64,
-) -> io::Result<()> {
+) -> Result<(), RepconError> {
     if page_format.get_page_header_size() + page_format.get_page_footer_size()
         > max_output_file_size
     {
-        let error_message = format!(
-            "Error: The maximum file size ({}) is too small to contain the page header and footer.",
-            max_output_file_size
-        );
-        Err(io::Error::new(io::ErrorKind::InvalidData, error_message))
+        Err(RepconError::OutputFileTooSmall {
+            max_output_file_size,
+        })
     } else {
         Ok(())
     }
 }
 
-/// Creates a new file for output, updates the file counter and size, and adds the file to the list.
-/// This function is used when the current file reaches its maximum size and a new file is needed.
-fn next_output_file(
-    output_directory: &Path,
-    output_file_counter: &mut u64,
-    output_name: &str,
-    current_output_file_size: &mut u64,
-    generated_output_files: &mut Vec<PathBuf>,
-    output_file: &mut File,
-    output_file_path: &mut PathBuf,
-) -> io::Result<()> {
-    *output_file_counter += 1;
-    *current_output_file_size = 0;
-    (*output_file, *output_file_path) =
-        create_new_output_file(output_directory, *output_file_counter, output_name)?;
-    generated_output_files.push(output_file_path.to_path_buf());
-    Ok(())
+/// An output chunk that was written to a sink.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// Name of the chunk, e.g. `output_1.txt`.
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Number of tokens, if they were counted while packing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u64>,
+}
+
+/// Tracks the current output chunk while the target files are written to a sink.
+struct ChunkWriter<'a> {
+    sink: &'a mut dyn ChunkSink,
+    output_name: &'a str,
+    token_counter: Option<&'a TokenCounter>,
+    chunk_counter: u64,
+    chunk_size: u64,
+    chunk_tokens: u64,
+    total_size: u64,
+    output_files: Vec<PathBuf>,
+    chunks: Vec<OutputChunk>,
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Starts the first chunk.
+    fn new(
+        sink: &'a mut dyn ChunkSink,
+        output_name: &'a str,
+        token_counter: Option<&'a TokenCounter>,
+    ) -> io::Result<Self> {
+        sink.begin_chunk(&output_file_name(output_name, 1))?;
+        Ok(Self {
+            sink,
+            output_name,
+            token_counter,
+            chunk_counter: 1,
+            chunk_size: 0,
+            chunk_tokens: 0,
+            total_size: 0,
+            output_files: Vec::new(),
+            chunks: Vec::new(),
+        })
+    }
+
+    /// Writes data to the current chunk. Returns the number of tokens of the data,
+    /// or 0 if tokens are not counted.
+    fn write(&mut self, data: &str) -> io::Result<u64> {
+        self.sink.write_all(data.as_bytes())?;
+        self.chunk_size += data.len() as u64;
+        self.total_size += data.len() as u64;
+        let tokens = self.token_counter.map_or(0, |counter| counter.count(data));
+        self.chunk_tokens += tokens;
+        Ok(tokens)
+    }
+
+    fn end_chunk(&mut self) -> io::Result<()> {
+        if let Some(path) = self.sink.end_chunk()? {
+            self.output_files.push(path);
+        }
+        self.chunks.push(OutputChunk {
+            name: output_file_name(self.output_name, self.chunk_counter),
+            size: self.chunk_size,
+            tokens: self.token_counter.map(|_| self.chunk_tokens),
+        });
+        Ok(())
+    }
+
+    /// Finishes the current chunk and starts the next one.
+    /// This is used when the current chunk reaches its maximum size.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.end_chunk()?;
+        self.chunk_counter += 1;
+        self.chunk_size = 0;
+        self.chunk_tokens = 0;
+        self.sink
+            .begin_chunk(&output_file_name(self.output_name, self.chunk_counter))
+    }
+}
+
+/// Counts the bytes read from a target file.
+struct ByteCounter<R> {
+    inner: R,
+    bytes: u64,
+}
+impl<R: Read> Read for ByteCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+/// Why a target file was left out of the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file could not be opened.
+    Unreadable(String),
+    /// The file is not valid UTF-8 text. The lines before the first invalid one are kept.
+    NotText,
+}
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unreadable(message) => write!(f, "unreadable: {}", message),
+            Self::NotText => write!(f, "not a text file"),
+        }
+    }
+}
+
+/// A target file that was left out of the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    /// Path shown for the file in the output.
+    pub display_path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// A secret that was replaced with a placeholder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactedSecret {
+    /// Name of the rule that detected the secret.
+    pub rule: String,
+    /// Display path and line number of the secret, e.g. `src/config.rs:12`.
+    pub location: String,
+}
+
+/// A target file that was packed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedFile {
+    pub path: PathBuf,
+    /// Size of the file in bytes, as read while packing it.
+    pub bytes: u64,
+    /// Number of lines read from the file, including lines removed by secret redaction.
+    pub lines: u64,
+    /// Number of tokens of the packed content, if they were counted while packing.
+    pub tokens: Option<u64>,
+}
+
+/// The result of packing target files into output files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackResult {
+    /// Paths of the generated output files. Empty if the sink does not write to files.
+    pub output_files: Vec<PathBuf>,
+    /// Number of output chunks.
+    pub chunk_count: u64,
+    /// The output chunks, in the order they were written.
+    pub chunks: Vec<OutputChunk>,
+    /// Total size of the output chunks in bytes.
+    pub output_size: u64,
+    /// Target files that were packed.
+    pub packed_files: Vec<PackedFile>,
+    pub skipped_files: Vec<SkippedFile>,
+    pub redacted_secrets: Vec<RedactedSecret>,
 }
 
 /// Splits the target files into chunks based on a maximum file size.
@@ -94,92 +239,196 @@ pub fn split_files_into_chunks(
     output_directory: &Path,
     max_output_file_size: u64,
     output_name: &str,
-) -> io::Result<Vec<PathBuf>> {
-    let mut generated_output_files = Vec::new();
-    let mut output_file_counter: u64 = 1;
-    let mut current_output_file_size: u64 = 0;
+) -> Result<Vec<PathBuf>, RepconError> {
+    let target_files: Vec<TargetFile> = target_files
+        .iter()
+        .map(|path| TargetFile::new(path.clone(), target_files_root_path))
+        .collect();
+    split_target_files_into_chunks(
+        &target_files,
+        output_directory,
+        max_output_file_size,
+        output_name,
+        None,
+    )
+}
+
+/// Splits the target files into chunks based on a maximum file size,
+/// using the display path of each target file in the page headers and footers.
+/// This allows files collected from several roots to be packed into a single output set.
+/// Returns a vector of paths to the generated files.
+///
+/// If a secret redactor is given, every line is scanned for secrets before being written.
+/// Detected secrets are replaced with placeholders and reported on standard error.
+///
+/// # Errors
+///
+/// Same as [`split_files_into_chunks`]. Also returns an `Err` when a secret is detected
+/// and the redactor is configured to fail on secrets.
+pub fn split_target_files_into_chunks(
+    target_files: &[TargetFile],
+    output_directory: &Path,
+    max_output_file_size: u64,
+    output_name: &str,
+    secret_redactor: Option<&SecretRedactor>,
+) -> Result<Vec<PathBuf>, RepconError> {
+    let result = pack_target_files(
+        target_files,
+        &mut DirectorySink::new(output_directory),
+        max_output_file_size,
+        output_name,
+        secret_redactor,
+        None,
+    )?;
+    print_pack_warnings(&result.skipped_files, &result.redacted_secrets);
+    Ok(result.output_files)
+}
+
+/// Prints the skipped files and the redacted secrets of a pack on standard error,
+/// as [`split_target_files_into_chunks`] and the `repcon` command report them.
+pub fn print_pack_warnings(skipped_files: &[SkippedFile], redacted_secrets: &[RedactedSecret]) {
+    for skipped_file in skipped_files {
+        match &skipped_file.reason {
+            SkipReason::Unreadable(message) => {
+                eprintln!("Failed to open file {:?}: {}", skipped_file.path, message)
+            }
+            SkipReason::NotText => eprintln!("Skipping non-text file: {:?}", skipped_file.path),
+        }
+    }
+    for secret in redacted_secrets {
+        eprintln!("Redacted secret ({}) in {}", secret.rule, secret.location);
+    }
+}
+
+/// Packs the target files like [`split_target_files_into_chunks`], but writes the output
+/// chunks to a sink and returns what was packed, skipped and redacted instead of reporting
+/// it on standard error.
+///
+/// If a token counter is given, the tokens of each chunk and packed file are counted
+/// as they are written, without reading the output again.
+pub fn pack_target_files(
+    target_files: &[TargetFile],
+    sink: &mut dyn ChunkSink,
+    max_output_file_size: u64,
+    output_name: &str,
+    secret_redactor: Option<&SecretRedactor>,
+    token_counter: Option<&TokenCounter>,
+) -> Result<PackResult, RepconError> {
+    let mut result = PackResult::default();
     let mut current_target_file_name: String;
     let mut page_format: PageFormat;
 
-    // Create the first file
-    let (mut output_file, mut output_file_path) =
-        create_new_output_file(output_directory, output_file_counter, output_name)?;
-    generated_output_files.push(output_file_path.clone());
+    // Start the first chunk
+    let mut output = ChunkWriter::new(sink, output_name, token_counter)?;
 
-    for target_file_path in target_files {
-        current_target_file_name = match target_file_path.to_str() {
+    for target_file in target_files {
+        let target_file_path = &target_file.path;
+        current_target_file_name = match target_file.display_path.to_str() {
             Some(name) => name.to_string(),
             None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Target File path contains invalid UTF-8 characters",
-                ));
+                return Err(RepconError::InvalidPath {
+                    path: target_file.display_path.clone(),
+                    reason: "Target file path contains invalid UTF-8 characters".to_string(),
+                });
             }
         };
 
         let file = match File::open(target_file_path) {
             Ok(file) => file,
             Err(e) => {
-                eprintln!("Failed to open file {:?}: {}", target_file_path, e);
+                result.skipped_files.push(SkippedFile {
+                    path: target_file_path.clone(),
+                    display_path: target_file.display_path.clone(),
+                    reason: SkipReason::Unreadable(e.to_string()),
+                });
                 continue;
             }
         };
 
-        page_format = PageFormat::new(current_target_file_name, target_files_root_path);
+        page_format = PageFormat::new(current_target_file_name, None);
         check_max_output_file_size(&page_format, max_output_file_size)?;
 
-        if current_output_file_size + page_format.header_size + page_format.footer_size
+        if output.chunk_size + page_format.header_size + page_format.footer_size
             > max_output_file_size
         {
-            next_output_file(
-                output_directory,
-                &mut output_file_counter,
-                output_name,
-                &mut current_output_file_size,
-                &mut generated_output_files,
-                &mut output_file,
-                &mut output_file_path.clone(),
-            )?;
+            output.next_chunk()?;
         }
 
-        write!(output_file, "{}", page_format.header)?;
-        current_output_file_size += page_format.header_size;
-
-        let reader = BufReader::new(file);
-        for line_result in reader.lines() {
+        output.write(&page_format.header)?;
+
+        let mut secret_scanner = secret_redactor.map(|redactor| redactor.scanner());
+        let mut reader = BufReader::new(ByteCounter {
+            inner: file,
+            bytes: 0,
+        });
+        let mut is_text = true;
+        let mut line_count = 0;
+        let mut token_count = 0;
+        for (line_index, line_result) in (&mut reader).lines().enumerate() {
             if line_result.is_err() {
-                eprintln!("Skipping non-text file: {:?}", target_file_path);
+                is_text = false;
                 break;
             }
-            let line = line_result.unwrap();
-            let line_size = line.as_bytes().len() as u64 + 1; // +1 for the newline character
-
-            if current_output_file_size + line_size + page_format.footer_size > max_output_file_size
-            {
-                write!(output_file, "{}", page_format.footer)?;
-
-                next_output_file(
-                    output_directory,
-                    &mut output_file_counter,
-                    output_name,
-                    &mut current_output_file_size,
-                    &mut generated_output_files,
-                    &mut output_file,
-                    &mut output_file_path,
-                )?;
+            line_count += 1;
+            let mut line = line_result.unwrap();
+
+            if let (Some(scanner), Some(redactor)) = (&mut secret_scanner, secret_redactor) {
+                let redacted = scanner.redact_line(&line);
+                for secret in &redacted.secrets {
+                    let location = format!("{}:{}", page_format.file_path, line_index + 1);
+                    if redactor.fail_on_secrets {
+                        return Err(RepconError::SecretDetected {
+                            secret: secret.to_string(),
+                            location,
+                        });
+                    }
+                    result.redacted_secrets.push(RedactedSecret {
+                        rule: secret.to_string(),
+                        location,
+                    });
+                }
+                match redacted.line {
+                    Some(redacted_line) => line = redacted_line,
+                    None => continue,
+                }
+            }
+
+            let line_size = line.len() as u64 + 1; // +1 for the newline character
+
+            if output.chunk_size + line_size + page_format.footer_size > max_output_file_size {
+                output.write(&page_format.footer)?;
+                output.next_chunk()?;
 
                 page_format.increment_page_number();
                 check_max_output_file_size(&page_format, max_output_file_size)?;
-                write!(output_file, "{}", page_format.header)?;
-                current_output_file_size += page_format.header_size;
+                output.write(&page_format.header)?;
             }
 
-            writeln!(output_file, "{}", line)?;
-            current_output_file_size += line_size;
+            line.push('\n');
+            token_count += output.write(&line)?;
+        }
+        output.write(&page_format.footer)?;
+        if is_text {
+            result.packed_files.push(PackedFile {
+                path: target_file_path.clone(),
+                bytes: reader.get_ref().bytes,
+                lines: line_count,
+                tokens: token_counter.map(|_| token_count),
+            });
+        } else {
+            result.skipped_files.push(SkippedFile {
+                path: target_file_path.clone(),
+                display_path: target_file.display_path.clone(),
+                reason: SkipReason::NotText,
+            });
         }
-        write!(output_file, "{}", page_format.footer)?;
     }
-    Ok(generated_output_files)
+    output.end_chunk()?;
+    result.output_files = output.output_files;
+    result.chunk_count = output.chunk_counter;
+    result.chunks = output.chunks;
+    result.output_size = output.total_size;
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -282,7 +531,10 @@ mod split_tests {
             output_name,
         );
 
-        assert!(result.is_err());
+        assert!(matches!(
+            result,
+            Err(RepconError::OutputFileTooSmall { .. })
+        ));
         Ok(())
     }
 
@@ -317,4 +569,71 @@ mod split_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_split_target_files_uses_display_path() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("main.rs");
+        let mut test_file = File::create(&file_path)?;
+        writeln!(test_file, "fn main() {{}}")?;
+        let files = vec![TargetFile {
+            path: file_path,
+            display_path: PathBuf::from("backend/src/main.rs"),
+        }];
+
+        let generated_output_files =
+            split_target_files_into_chunks(&files, temp_dir.path(), 300, "output", None)?;
+
+        assert_eq!(generated_output_files.len(), 1);
+        let generated_file_content = fs::read_to_string(&generated_output_files[0])?;
+        assert!(generated_file_content.contains("// START OF CODE BLOCK: backend/src/main.rs"));
+        assert!(generated_file_content.contains("// END OF CODE BLOCK: backend/src/main.rs"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_target_files_redacts_secrets() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join(".env");
+        let mut test_file = File::create(&file_path)?;
+        writeln!(
+            test_file,
+            "OPENAI_API_KEY=sk-abcdefghij0123456789ABCDEFGHIJ"
+        )?;
+        writeln!(test_file, "DEBUG=true")?;
+        let files = vec![TargetFile::new(file_path, Some(temp_dir.path()))];
+        let mut redactor = SecretRedactor::new();
+
+        let generated_output_files = split_target_files_into_chunks(
+            &files,
+            temp_dir.path(),
+            300,
+            "output",
+            Some(&redactor),
+        )?;
+
+        let generated_file_content = fs::read_to_string(&generated_output_files[0])?;
+        assert!(generated_file_content.contains("OPENAI_API_KEY=[REDACTED:openai_api_key]\n"));
+        assert!(generated_file_content.contains("DEBUG=true\n"));
+        assert!(!generated_file_content.contains("sk-abcdefghij"));
+
+        redactor.fail_on_secrets = true;
+        let result =
+            split_target_files_into_chunks(&files, temp_dir.path(), 300, "output", Some(&redactor));
+        assert!(matches!(
+            result,
+            Err(RepconError::SecretDetected { location, .. }) if location.ends_with(":1")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_output_file_name() {
+        assert!(is_output_file_name("output_1.txt", "output"));
+        assert!(is_output_file_name("out/dir/output_12.txt", "output"));
+        assert!(!is_output_file_name("output_.txt", "output"));
+        assert!(!is_output_file_name("output_1.md", "output"));
+        assert!(!is_output_file_name("other_1.txt", "output"));
+        assert!(!is_output_file_name("output_final_1.txt", "output"));
+    }
 }
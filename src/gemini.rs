@@ -0,0 +1,299 @@
+use crate::openai::{Retry, RetryPolicy, UploadError, UploadedFile};
+use crate::uploader::{
+    file_body, send_with_retry, upload_name, RequestFailure, UploadProgress, Uploader,
+};
+use crate::RepconError;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::future::Future;
+use std::path::Path;
+
+/// Default base URL of the Gemini API.
+pub const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com";
+
+/// Maximum size of a file uploaded to the Gemini Files API, in bytes.
+pub const GEMINI_MAX_FILE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// A file object returned by the Gemini Files API.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiFile {
+    /// Resource name of the file, e.g. `files/abc-123`.
+    name: String,
+    #[serde(default)]
+    display_name: Option<String>,
+    /// The size is encoded as a string.
+    #[serde(default)]
+    size_bytes: Option<String>,
+}
+impl From<GeminiFile> for UploadedFile {
+    fn from(file: GeminiFile) -> Self {
+        Self {
+            filename: file.display_name.unwrap_or_else(|| file.name.clone()),
+            bytes: file
+                .size_bytes
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            id: file.name,
+            created_at: 0,
+        }
+    }
+}
+
+/// Uploads the output files to the Gemini Files API.
+/// Files are identified by their resource name, e.g. `files/abc-123`.
+/// Gemini deletes uploaded files automatically after 48 hours.
+/// Failed requests are retried like the requests to OpenAI.
+pub struct GeminiUploader {
+    http: reqwest::Client,
+    api_base: String,
+    retry_policy: RetryPolicy,
+}
+impl GeminiUploader {
+    /// Constructs a new `GeminiUploader` authenticated with the API key.
+    pub fn new(
+        api_key: &str,
+        api_base: &str,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, RepconError> {
+        let mut api_key = HeaderValue::from_str(api_key).map_err(|_| {
+            RepconError::Config("Invalid characters in the Gemini API key".to_string())
+        })?;
+        api_key.set_sensitive(true);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-goog-api-key", api_key);
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(retry_policy.timeout)
+            .build()
+            .map_err(|e| RepconError::Config(e.to_string()))?;
+        Ok(Self {
+            http,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            retry_policy,
+        })
+    }
+
+    /// Constructs a new `GeminiUploader` authenticated with the `GEMINI_API_KEY`
+    /// (or `GOOGLE_API_KEY`) environment variable.
+    /// The base URL can be changed with `GEMINI_BASE_URL`.
+    pub fn from_env(retry_policy: RetryPolicy) -> Result<Self, RepconError> {
+        let api_key = env::var("GEMINI_API_KEY")
+            .or_else(|_| env::var("GOOGLE_API_KEY"))
+            .map_err(|_| {
+                RepconError::Config("GEMINI_API_KEY not found in environment.".to_string())
+            })?;
+        let api_base = env::var("GEMINI_BASE_URL").unwrap_or_else(|_| GEMINI_API_BASE.to_string());
+        Self::new(&api_key, &api_base, retry_policy)
+    }
+
+    /// Sends a request built by `build_request`, retrying the failures selected by `retry`.
+    /// Returns the headers and body of the successful response.
+    async fn send<Fut>(
+        &self,
+        retry: Retry,
+        build_request: impl Fn() -> Fut,
+    ) -> Result<(HeaderMap, String), RequestFailure>
+    where
+        Fut: Future<Output = Result<RequestBuilder, RequestFailure>>,
+    {
+        send_with_retry(&self.retry_policy, retry, build_request, failure).await
+    }
+}
+
+/// Describes a non-successful response from its error object.
+fn failure(status: StatusCode, body: &str) -> RequestFailure {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        error: ErrorDetail,
+    }
+    #[derive(Deserialize)]
+    struct ErrorDetail {
+        message: Option<String>,
+        status: Option<String>,
+    }
+
+    let (code, message) = match serde_json::from_str::<ErrorBody>(body) {
+        Ok(ErrorBody { error }) => (error.status, error.message.unwrap_or_default()),
+        Err(_) => (None, body.trim().to_string()),
+    };
+    RequestFailure {
+        status: Some(status.as_u16()),
+        code,
+        message,
+    }
+}
+
+/// Parses a JSON response of the Gemini API.
+fn parse<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, RequestFailure> {
+    serde_json::from_str(body)
+        .map_err(|e| RequestFailure::new(format!("Unexpected response from Gemini: {}", e)))
+}
+
+#[async_trait]
+impl Uploader for GeminiUploader {
+    fn destination(&self) -> String {
+        "gemini".to_string()
+    }
+
+    /// Uploads a file with the resumable upload protocol:
+    /// the first request creates an upload session, the second one sends the contents.
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        let name = upload_name(file_path)?;
+        let size = tokio::fs::metadata(file_path)
+            .await
+            .map_err(|e| UploadError::new(file_path, e))?
+            .len();
+
+        // Both requests may act on the upload, so they are only retried if not processed
+        let name = &name;
+        let start_url = &format!("{}/upload/v1beta/files", self.api_base);
+        let (headers, _) = self
+            .send(Retry::Unprocessed, || async move {
+                Ok(self
+                    .http
+                    .post(start_url)
+                    .header("X-Goog-Upload-Protocol", "resumable")
+                    .header("X-Goog-Upload-Command", "start")
+                    .header("X-Goog-Upload-Header-Content-Length", size)
+                    .header("X-Goog-Upload-Header-Content-Type", "text/plain")
+                    .json(&json!({ "file": { "display_name": name } })))
+            })
+            .await
+            .map_err(|e| e.into_upload_error(file_path))?;
+        let upload_url = headers
+            .get("x-goog-upload-url")
+            .and_then(|url| url.to_str().ok())
+            .ok_or_else(|| UploadError::new(file_path, "Gemini did not return an upload URL"))?;
+
+        let (_, body) = self
+            .send(Retry::Unprocessed, || async move {
+                // The file is opened again for every attempt
+                let (contents, size) = file_body(file_path, progress)
+                    .await
+                    .map_err(RequestFailure::new)?;
+                Ok(self
+                    .http
+                    .post(upload_url)
+                    .header("X-Goog-Upload-Offset", "0")
+                    .header("X-Goog-Upload-Command", "upload, finalize")
+                    .header("Content-Length", size)
+                    .body(contents))
+            })
+            .await
+            .map_err(|e| e.into_upload_error(file_path))?;
+
+        #[derive(Deserialize)]
+        struct UploadResponse {
+            file: GeminiFile,
+        }
+        parse::<UploadResponse>(&body)
+            .map(|response| UploadedFile::from(response.file))
+            .map_err(|e| e.into_upload_error(file_path))
+    }
+
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError> {
+        let url = &format!("{}/v1beta/{}", self.api_base, file_id);
+        self.send(Retry::Always, || async move { Ok(self.http.delete(url)) })
+            .await
+            .map(|_| ())
+            .map_err(|e| e.into_error(&format!("delete {}", file_id)))
+    }
+
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct FileList {
+            #[serde(default)]
+            files: Vec<GeminiFile>,
+            next_page_token: Option<String>,
+        }
+
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let url = &format!("{}/v1beta/files", self.api_base);
+            let token = page_token.as_deref();
+            let page: FileList = self
+                .send(Retry::Always, || async move {
+                    let mut request = self.http.get(url).query(&[("pageSize", "100")]);
+                    if let Some(page_token) = token {
+                        request = request.query(&[("pageToken", page_token)]);
+                    }
+                    Ok(request)
+                })
+                .await
+                .and_then(|(_, body)| parse(&body))
+                .map_err(|e| e.into_error("list files"))?;
+            files.extend(page.files.into_iter().map(UploadedFile::from));
+            match page.next_page_token {
+                Some(next_page_token) if !next_page_token.is_empty() => {
+                    page_token = Some(next_page_token)
+                }
+                _ => return Ok(files),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod gemini_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use std::fs;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_gemini_resumable_upload() -> io::Result<()> {
+        let server_url = Arc::new(Mutex::new(String::new()));
+        let upload_url = Arc::clone(&server_url);
+        let server = MockServer::start(move |request| {
+            if request.path.starts_with("/upload/v1beta/files") {
+                let upload_url = format!("{}/upload-session/1", upload_url.lock().unwrap());
+                MockResponse::new(200, "{}").with_header("X-Goog-Upload-URL", &upload_url)
+            } else {
+                MockResponse::new(
+                    200,
+                    r#"{"file": {"name": "files/abc-123", "displayName": "output_1.txt", "sizeBytes": "5", "state": "ACTIVE"}}"#,
+                )
+            }
+        })
+        .await;
+        *server_url.lock().unwrap() = server.url.clone();
+        let uploader = GeminiUploader::new("test-key", &server.url, RetryPolicy::default())?;
+        let dir = tempdir()?;
+        let file_path = dir.path().join("output_1.txt");
+        fs::write(&file_path, "hello")?;
+
+        let uploaded_file = uploader.upload(&file_path, None).await.unwrap();
+        assert_eq!(uploaded_file.id, "files/abc-123");
+        assert_eq!(uploaded_file.filename, "output_1.txt");
+        assert_eq!(uploaded_file.bytes, 5);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("x-goog-api-key"), Some("test-key"));
+        assert_eq!(requests[0].header("x-goog-upload-command"), Some("start"));
+        assert_eq!(
+            requests[0].header("x-goog-upload-header-content-length"),
+            Some("5")
+        );
+        assert_eq!(requests[1].path, "/upload-session/1");
+        assert_eq!(
+            requests[1].header("x-goog-upload-command"),
+            Some("upload, finalize")
+        );
+        assert_eq!(requests[1].body, b"hello");
+        Ok(())
+    }
+}
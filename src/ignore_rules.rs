@@ -0,0 +1,421 @@
+use crate::{root_ignore_files, target_walk_builder, InputRoot, RepconError, SkipReason};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Ignore files read by the directory walker in each directory, in order of precedence.
+const DIRECTORY_IGNORE_FILES: [&str; 3] = [".ignore", ".gitignore", ".git/info/exclude"];
+
+/// The rule that excluded a file or directory from the target files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExclusionRule {
+    /// A pattern given on the command line with `--ignore`.
+    IgnorePattern(String),
+    /// A pattern in an ignore file such as `.gitignore` or `.repconignore`.
+    IgnoreFile {
+        pattern: String,
+        path: PathBuf,
+        line: Option<usize>,
+    },
+    /// The file or directory is hidden, i.e. its name starts with a dot.
+    Hidden,
+    /// A rule that cannot be attributed, e.g. from the global gitignore file.
+    Other,
+}
+impl fmt::Display for ExclusionRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::IgnorePattern(pattern) => write!(f, "ignore pattern `{}`", pattern),
+            Self::IgnoreFile {
+                pattern,
+                path,
+                line: Some(line),
+            } => write!(f, "`{}` in {}:{}", pattern, path.display(), line),
+            Self::IgnoreFile {
+                pattern,
+                path,
+                line: None,
+            } => write!(f, "`{}` in {}", pattern, path.display()),
+            Self::Hidden => write!(f, "hidden file"),
+            Self::Other => write!(f, "another ignore rule"),
+        }
+    }
+}
+
+/// A file or directory under a root that is not part of the target files.
+/// The contents of an excluded directory are not listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcludedPath {
+    pub path: PathBuf,
+    /// Path relative to the root and prefixed with its alias, as in the output.
+    pub display_path: PathBuf,
+    pub is_dir: bool,
+    pub rule: ExclusionRule,
+}
+
+/// An ignore file loaded with the lines it was built from, to find the line of a pattern.
+struct IgnoreFile {
+    path: PathBuf,
+    lines: Vec<String>,
+    matcher: Gitignore,
+}
+impl IgnoreFile {
+    /// Loads an ignore file whose patterns are relative to `base`.
+    /// Returns `None` if the file does not exist or cannot be read.
+    fn load(base: &Path, path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut builder = GitignoreBuilder::new(base);
+        for line in content.lines() {
+            // Invalid patterns are rejected when the target files are collected
+            let _ = builder.add_line(Some(path.to_path_buf()), line);
+        }
+        Some(Self {
+            path: path.to_path_buf(),
+            lines: content.lines().map(String::from).collect(),
+            matcher: builder.build().ok()?,
+        })
+    }
+
+    fn matched(&self, path: &Path, is_dir: bool) -> Match<ExclusionRule> {
+        self.matcher
+            .matched_path_or_any_parents(path, is_dir)
+            .map(|glob| ExclusionRule::IgnoreFile {
+                pattern: glob.original().to_string(),
+                path: self.path.clone(),
+                line: self
+                    .lines
+                    .iter()
+                    .position(|line| line.trim_end() == glob.original())
+                    .map(|index| index + 1),
+            })
+    }
+}
+
+/// Finds the rule that excluded a path from the target files of a root.
+///
+/// The rules are checked in the order the directory walker applies them: ignore patterns,
+/// `.repconignore` files, `.ignore`, `.gitignore` and Git exclude files from the deepest
+/// directory up to the repository root, and finally hidden files.
+pub(crate) struct ExclusionRules {
+    root: PathBuf,
+    ignore_patterns: Gitignore,
+    repconignore_files: Vec<IgnoreFile>,
+    directory_ignore_files: HashMap<PathBuf, Vec<IgnoreFile>>,
+}
+impl ExclusionRules {
+    /// Loads the rules of a root. The root must exist.
+    pub(crate) fn new(
+        root: &Path,
+        ignore_patterns: &[String],
+        ignore_files: &[PathBuf],
+    ) -> Result<Self, RepconError> {
+        let root = fs::canonicalize(root)?;
+        let mut builder = GitignoreBuilder::new(&root);
+        for pattern in ignore_patterns {
+            builder.add_line(None, pattern)?;
+        }
+        Ok(Self {
+            ignore_patterns: builder.build()?,
+            repconignore_files: ignore_files
+                .iter()
+                .filter_map(|path| IgnoreFile::load(&root, path))
+                .collect(),
+            directory_ignore_files: HashMap::new(),
+            root,
+        })
+    }
+
+    /// Returns the rule that excludes a path, given relative to the root.
+    pub(crate) fn rule(&mut self, relative_path: &Path, is_dir: bool) -> ExclusionRule {
+        let path = self.root.join(relative_path);
+        if let Match::Ignore(glob) = self
+            .ignore_patterns
+            .matched_path_or_any_parents(&path, is_dir)
+        {
+            return ExclusionRule::IgnorePattern(glob.original().to_string());
+        }
+        for ignore_file in &self.repconignore_files {
+            if let Match::Ignore(rule) = ignore_file.matched(&path, is_dir) {
+                return rule;
+            }
+        }
+
+        for name in DIRECTORY_IGNORE_FILES {
+            for dir in path.ancestors().skip(1) {
+                let ignore_files = self
+                    .directory_ignore_files
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| {
+                        DIRECTORY_IGNORE_FILES
+                            .iter()
+                            .filter_map(|name| IgnoreFile::load(dir, &dir.join(name)))
+                            .collect()
+                    });
+                let ignore_file = ignore_files
+                    .iter()
+                    .find(|ignore_file| ignore_file.path.ends_with(name));
+                match ignore_file.map(|ignore_file| ignore_file.matched(&path, is_dir)) {
+                    Some(Match::Ignore(rule)) => return rule,
+                    // Whitelisted files are not hidden either
+                    Some(Match::Whitelist(_)) => return ExclusionRule::Other,
+                    _ => {}
+                }
+                if dir.join(".git").exists() {
+                    break;
+                }
+            }
+        }
+
+        let is_hidden = relative_path.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .is_some_and(|name| name.starts_with('.') && name != "." && name != "..")
+        });
+        if is_hidden {
+            ExclusionRule::Hidden
+        } else {
+            ExclusionRule::Other
+        }
+    }
+}
+
+/// Why a file or directory is or is not part of the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Explanation {
+    /// The file, or the files of the directory that are not excluded, are packed.
+    Included,
+    /// The path is excluded by an ignore rule.
+    Excluded(ExclusionRule),
+    /// The file is collected, but left out of the output when it is packed.
+    Skipped(SkipReason),
+}
+
+/// Returns an error if the root directory does not exist.
+fn check_root(root: &InputRoot) -> Result<(), RepconError> {
+    if root.path.is_dir() {
+        Ok(())
+    } else {
+        Err(RepconError::InvalidPath {
+            path: root.path.clone(),
+            reason: "Root directory not found".to_string(),
+        })
+    }
+}
+
+/// Explains why a file or directory under a root is or is not part of the output,
+/// using the same rules as [`collect_excluded_paths`].
+pub fn explain_path(
+    root: &InputRoot,
+    path: &Path,
+    ignore_patterns: &[String],
+    reconignore_path: Option<&String>,
+) -> Result<Explanation, RepconError> {
+    check_root(root)?;
+    let invalid_path = |reason: String| RepconError::InvalidPath {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let metadata = fs::metadata(path).map_err(|_| invalid_path("File not found".to_string()))?;
+    let relative_path = fs::canonicalize(path)?
+        .strip_prefix(fs::canonicalize(&root.path)?)
+        .map(Path::to_path_buf)
+        .map_err(|_| {
+            invalid_path(format!(
+                "Path is not under the root directory {}",
+                root.path.display()
+            ))
+        })?;
+
+    // Only walk the directories leading to the path
+    let target = root.path.join(&relative_path);
+    let ignore_files = root_ignore_files(root, reconignore_path);
+    let mut walk_builder = target_walk_builder(&root.path, ignore_patterns, &ignore_files)?;
+    let walked_target = target.clone();
+    walk_builder.filter_entry(move |entry| walked_target.starts_with(entry.path()));
+    let is_included = walk_builder
+        .build()
+        .flatten()
+        .any(|entry| entry.path() == target);
+    if !is_included {
+        let mut rules = ExclusionRules::new(&root.path, ignore_patterns, &ignore_files)?;
+        return Ok(Explanation::Excluded(
+            rules.rule(&relative_path, metadata.is_dir()),
+        ));
+    }
+
+    if metadata.is_dir() {
+        return Ok(Explanation::Included);
+    }
+    Ok(match fs::read(path) {
+        Err(e) => Explanation::Skipped(SkipReason::Unreadable(e.to_string())),
+        Ok(content) if std::str::from_utf8(&content).is_err() => {
+            Explanation::Skipped(SkipReason::NotText)
+        }
+        Ok(_) => Explanation::Included,
+    })
+}
+
+/// Lists the files and directories of a root that are excluded from its target files,
+/// with the rule that excluded each of them. Uses the same rules as
+/// [`collect_target_files_from_roots`](crate::collect_target_files_from_roots).
+pub fn collect_excluded_paths(
+    root: &InputRoot,
+    ignore_patterns: &[String],
+    reconignore_path: Option<&String>,
+) -> Result<Vec<ExcludedPath>, RepconError> {
+    check_root(root)?;
+    let ignore_files = root_ignore_files(root, reconignore_path);
+    let included: HashSet<PathBuf> =
+        target_walk_builder(&root.path, ignore_patterns, &ignore_files)?
+            .build()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .collect();
+    let mut rules = ExclusionRules::new(&root.path, ignore_patterns, &ignore_files)?;
+
+    let mut excluded = Vec::new();
+    let mut dirs = vec![root.path.clone()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        // Directories are visited depth-first, in order of their names
+        for entry in entries.into_iter().rev() {
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            if included.contains(&path) {
+                if file_type.is_dir() {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            let relative_path = path.strip_prefix(&root.path).unwrap_or(&path);
+            excluded.push(ExcludedPath {
+                rule: rules.rule(relative_path, file_type.is_dir()),
+                display_path: root.display_path(&path),
+                is_dir: file_type.is_dir(),
+                path,
+            });
+        }
+    }
+    excluded.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(excluded)
+}
+
+#[cfg(test)]
+mod ignore_rules_tests {
+    use super::*;
+    use std::io;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_excluded_paths_are_attributed_to_rules() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git/info"))?;
+        fs::create_dir_all(root.join("build"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join(".gitignore"), "# Build output\nbuild/\n")?;
+        fs::write(root.join(".repconignore"), "*.md\n")?;
+        fs::write(root.join("build/app"), "binary")?;
+        fs::write(root.join("src/main.rs"), "fn main() {}\n")?;
+        fs::write(root.join("src/notes.log"), "log\n")?;
+        fs::write(root.join("README.md"), "# Title\n")?;
+
+        let excluded = collect_excluded_paths(
+            &InputRoot::new(root, Some("repo".to_string())),
+            &["*.log".to_string()],
+            None,
+        )?;
+        let rules: Vec<(String, bool, String)> = excluded
+            .iter()
+            .map(|excluded| {
+                (
+                    excluded.display_path.display().to_string(),
+                    excluded.is_dir,
+                    excluded.rule.to_string(),
+                )
+            })
+            .collect();
+
+        let canonical_root = fs::canonicalize(root)?;
+        assert_eq!(
+            rules,
+            vec![
+                ("repo/.git".to_string(), true, "hidden file".to_string()),
+                (
+                    "repo/.gitignore".to_string(),
+                    false,
+                    "hidden file".to_string()
+                ),
+                (
+                    "repo/.repconignore".to_string(),
+                    false,
+                    "hidden file".to_string()
+                ),
+                (
+                    "repo/README.md".to_string(),
+                    false,
+                    format!("`*.md` in {}:1", root.join(".repconignore").display())
+                ),
+                (
+                    "repo/build".to_string(),
+                    true,
+                    format!(
+                        "`build/` in {}:2",
+                        canonical_root.join(".gitignore").display()
+                    )
+                ),
+                (
+                    "repo/src/notes.log".to_string(),
+                    false,
+                    "ignore pattern `*.log`".to_string()
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_path() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join("target/debug"))?;
+        fs::write(root.join(".gitignore"), "target/\n")?;
+        fs::write(root.join("target/debug/app"), "binary")?;
+        fs::write(root.join("main.rs"), "fn main() {}\n")?;
+        fs::write(root.join("image.png"), [0x89, 0x50, 0xff])?;
+
+        let root = InputRoot::new(root, None);
+        let explain = |path: &str| explain_path(&root, &root.path.join(path), &[], None);
+        assert_eq!(explain("main.rs")?, Explanation::Included);
+        assert_eq!(
+            explain("image.png")?,
+            Explanation::Skipped(SkipReason::NotText)
+        );
+        assert_eq!(
+            explain("target/debug/app")?,
+            Explanation::Excluded(ExclusionRule::IgnoreFile {
+                pattern: "target/".to_string(),
+                path: fs::canonicalize(&root.path)?.join(".gitignore"),
+                line: Some(1),
+            })
+        );
+        assert!(matches!(
+            explain("missing.rs"),
+            Err(RepconError::InvalidPath { .. })
+        ));
+        assert!(matches!(
+            explain_path(&root, Path::new("/"), &[], None),
+            Err(RepconError::InvalidPath { .. })
+        ));
+        Ok(())
+    }
+}
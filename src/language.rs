@@ -0,0 +1,72 @@
+use std::path::Path;
+
+/// Name used for files whose language could not be detected.
+pub const UNKNOWN_LANGUAGE: &str = "Other";
+
+/// Detects the language of a file from its name and extension.
+/// Returns `UNKNOWN_LANGUAGE` if the language is not recognized.
+pub fn detect_language(path: &Path) -> &'static str {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    match file_name {
+        "Dockerfile" | "Containerfile" => return "Dockerfile",
+        "Makefile" | "GNUmakefile" => return "Makefile",
+        "CMakeLists.txt" => return "CMake",
+        _ => {}
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "jsx" | "mjs" | "cjs" => "JavaScript",
+        "ts" | "tsx" | "mts" | "cts" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "scala" => "Scala",
+        "sh" | "bash" | "zsh" => "Shell",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" | "scss" | "sass" | "less" => "CSS",
+        "vue" => "Vue",
+        "svelte" => "Svelte",
+        "md" | "markdown" => "Markdown",
+        "json" => "JSON",
+        "yml" | "yaml" => "YAML",
+        "toml" => "TOML",
+        "xml" => "XML",
+        "tf" | "hcl" => "HCL",
+        "proto" => "Protocol Buffers",
+        "txt" | "rst" => "Text",
+        _ => UNKNOWN_LANGUAGE,
+    }
+}
+
+#[cfg(test)]
+mod language_tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language(Path::new("src/main.rs")), "Rust");
+        assert_eq!(detect_language(Path::new("web/App.TSX")), "TypeScript");
+        assert_eq!(
+            detect_language(Path::new("docker/Dockerfile")),
+            "Dockerfile"
+        );
+        assert_eq!(detect_language(Path::new("LICENSE")), UNKNOWN_LANGUAGE);
+    }
+}
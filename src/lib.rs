@@ -1,9 +1,53 @@
+mod error;
 mod openai;
 mod file_operations;
+mod ignore_rules;
 mod output_formatting;
 mod file_splitting;
+mod chunk_sink;
+mod bundle;
+mod file_ordering;
+mod language;
+mod dependency_ordering;
+mod secret_redaction;
+mod vector_store;
+mod assistant;
+mod sync;
+mod uploader;
+mod s3;
+mod webdav;
+mod anthropic;
+mod gemini;
+mod repcon;
+mod stats;
+mod cost_estimate;
+mod manifest;
+mod unpack;
+#[cfg(test)]
+mod mock_server;
 
+pub use error::*;
 pub use file_operations::*;
+pub use ignore_rules::*;
 pub use output_formatting::*;
 pub use file_splitting::*;
+pub use chunk_sink::*;
+pub use bundle::*;
 pub use openai::*;
+pub use file_ordering::*;
+pub use language::*;
+pub use dependency_ordering::*;
+pub use secret_redaction::*;
+pub use vector_store::*;
+pub use assistant::*;
+pub use sync::*;
+pub use uploader::*;
+pub use s3::*;
+pub use webdav::*;
+pub use anthropic::*;
+pub use gemini::*;
+pub use repcon::*;
+pub use stats::*;
+pub use cost_estimate::*;
+pub use manifest::*;
+pub use unpack::*;
@@ -1,47 +1,74 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 use dotenv::dotenv;
 use repcon::{
-    check_size_limits, collect_target_files, format_file_size, get_dir_size,
-    split_files_into_chunks, upload_file_to_openai,
+    estimate_cost, explain_path, format_file_size, get_target_files_size, is_output_file_name,
+    manifest_file_name, print_pack_warnings, read_file_list, unpack, upload_files_with,
+    AssistantSettings, BundleFormat, BundleSink, CostEstimate, Explanation, FileOrder, InputRoot,
+    Manifest, OpenAiClient, OpenAiConfig, OpenAiError, Plan, Pricing, Repcon, RepconBuilder,
+    RepconError, RetryPolicy, SecretRedactor, Stats, StdoutSink, SyncState, UploadDestination,
+    UploadError, UploadProgress, UploadedFile, VectorStore, DEFAULT_EMBEDDING_PRICE,
+    DEFAULT_INDEXING_TIMEOUT, DEFAULT_MAX_FILES, DEFAULT_MAX_FILE_SIZE, DEFAULT_STORAGE_DAYS,
+    DEFAULT_STORAGE_PRICE, DEFAULT_SYNC_STATE_FILE, OPENAI_API_BASE,
 };
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use tokio;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Interval between two checks of the indexing status of a vector store file batch.
+const VECTOR_STORE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Files smaller than this are uploaded without progress messages, in bytes.
+const PROGRESS_MIN_FILE_SIZE: u64 = 8 * 1024 * 1024;
 
 /// `repcon` is a Rust-based CLI tool designed to efficiently condense files within a repository.
 /// This tool aims to condense files into a maximum of 20 text documents, addressing the file upload limits on certain platforms.
 /// It was developed for creating uploadable files for the OpenAI's Assistants API Retrieval feature.
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Path to the repository's root directory
-    #[clap(value_parser)]
-    path_to_repo: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    /// Custom ignore patterns
-    #[clap(short = 'i', long = "ignore", value_parser)]
-    ignore_patterns: Vec<String>,
+    #[clap(flatten)]
+    target: TargetArgs,
 
-    /// Path to the repconignore file
+    /// Read the list of files to pack from a file, or from standard input if `-` is given,
+    /// instead of walking the repositories. Entries are NUL- or newline-delimited.
+    /// Ignore patterns do not apply to the listed files.
     #[clap(
-        short = 'r',
-        long = "repconignore",
+        long = "files-from",
         value_parser,
-        default_value = ".repconignore"
+        conflicts_with_all = ["ignore_patterns", "repconignore_path"]
     )]
-    repconignore_path: Option<String>,
+    files_from: Option<String>,
+
+    /// Order of the files in the output.
+    /// Defaults to `path`, or to the order of the list when `--files-from` is used.
+    #[clap(long = "sort", value_enum)]
+    order: Option<SortOrder>,
+
+    /// Glob patterns of files to put first with `--sort priority`, in order of priority.
+    /// Defaults to READMEs followed by common package manifests.
+    #[clap(long = "priority", value_parser)]
+    priority_patterns: Vec<String>,
 
     /// Maximum number of files to output
-    #[clap(short = 'f', long = "max-files", default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..1001))]
+    #[clap(short = 'f', long = "max-files", default_value_t = DEFAULT_MAX_FILES, value_parser = clap::value_parser!(u64).range(1..1001))]
     max_files: u64,
 
-    /// Maximum size of each output file in megabytes
-    #[clap(short = 's', long = "max-size", default_value_t = 540, value_parser = clap::value_parser!(u64).range(1..100001))]
-    max_file_size: u64,
-
     /// Path to the output directory for the condensed files
     #[clap(short = 'o', long = "output", value_parser, default_value = "output")]
     output_directory: String,
@@ -55,96 +82,1285 @@ struct Args {
     )]
     output_name: String,
 
+    /// Write the packed content to standard output instead of creating output files.
+    /// Status messages are printed to standard error.
+    #[clap(long = "stdout", conflicts_with_all = ["output_directory", "upload", "upload_to"])]
+    stdout: bool,
+
+    /// Write all output chunks into a single archive, `{output_name}.zip` or
+    /// `{output_name}.tar.gz` in the output directory, instead of separate files
+    #[clap(long = "bundle", value_enum, conflicts_with_all = ["stdout", "upload"])]
+    bundle: Option<BundleArg>,
+
+    /// Print the files that would be packed or excluded, and the output files that would be
+    /// produced, without writing or uploading anything
+    #[clap(long = "dry-run", conflicts_with = "stdout")]
+    dry_run: bool,
+
+    /// Count the tokens of the output files while packing, and print the estimated cost of
+    /// indexing them before any upload. The estimate is also written to the manifest.
+    /// With `--dry-run`, nothing is written.
+    #[clap(long = "estimate-cost", conflicts_with = "stdout")]
+    estimate_cost: bool,
+
+    /// Price of embedding one million tokens in US dollars, for the cost estimate
+    #[clap(long = "embedding-price", default_value_t = DEFAULT_EMBEDDING_PRICE)]
+    embedding_price: f64,
+
+    /// Price of storing one GB for one day in US dollars, for the cost estimate
+    #[clap(long = "storage-price", default_value_t = DEFAULT_STORAGE_PRICE)]
+    storage_price: f64,
+
+    /// Number of days of storage included in the cost estimate
+    #[clap(long = "storage-days", default_value_t = DEFAULT_STORAGE_DAYS)]
+    storage_days: u64,
+
     /// The OpenAI API key for file upload.
     /// If only `-u` is specified, the environment variable `OPENAI_API_KEY` is used.
     #[clap(short = 'u', long = "upload", value_parser)]
     upload: Option<Option<String>>,
+
+    #[clap(flatten)]
+    openai: OpenAiArgs,
+
+    /// Maximum number of files uploaded in parallel
+    #[clap(long = "upload-concurrency", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..65))]
+    upload_concurrency: u64,
+
+    /// Maximum number of attempts per upload, including the first one.
+    /// Connection errors, rate limits and 502 and 503 responses are retried with exponential
+    /// backoff. Timeouts and other server errors are not retried, as the file may have been created.
+    #[clap(long = "upload-attempts", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..101))]
+    upload_attempts: u32,
+
+    /// Timeout of a single upload attempt in seconds
+    #[clap(long = "upload-timeout", default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    upload_timeout: u64,
+
+    /// Additional destinations to publish the output files to: `openai`, `anthropic`, `gemini`,
+    /// `s3://BUCKET[/PREFIX]`, `webdav+https://HOST/PATH`, or a local directory
+    #[clap(long = "upload-to", value_parser)]
+    upload_to: Vec<String>,
+
+    /// Collection to add the files published with `--upload-to` to,
+    /// e.g. the name or ID of an OpenAI vector store
+    #[clap(long = "collection", requires = "upload_to")]
+    collection: Option<String>,
+
+    /// Upload only the output files that changed since the last sync,
+    /// and delete the previously uploaded copies of changed and removed files
+    #[clap(long = "sync", requires = "upload")]
+    sync: bool,
+
+    /// Path to the state file recording the files uploaded with `--sync`
+    #[clap(long = "sync-state", value_parser, default_value = DEFAULT_SYNC_STATE_FILE)]
+    sync_state: String,
+
+    /// Name or ID (`vs_...`) of a vector store to attach the uploaded files to.
+    /// A vector store with that name is created if there is none.
+    #[clap(long = "vector-store", requires = "upload")]
+    vector_store: Option<String>,
+
+    /// ID (`asst_...`) of an assistant whose `file_search` tool should search the vector store
+    #[clap(
+        long = "assistant",
+        requires = "vector_store",
+        group = "assistant_target"
+    )]
+    assistant: Option<String>,
+
+    /// Create an assistant with this name whose `file_search` tool searches the vector store
+    #[clap(
+        long = "create-assistant",
+        requires = "vector_store",
+        group = "assistant_target"
+    )]
+    create_assistant: Option<String>,
+
+    /// Instructions of the created or updated assistant
+    #[clap(long = "assistant-instructions", requires = "assistant_target")]
+    assistant_instructions: Option<String>,
+
+    /// Model of the created or updated assistant. Defaults to `gpt-4o` for created assistants.
+    #[clap(long = "assistant-model", requires = "assistant_target")]
+    assistant_model: Option<String>,
 }
 
-/// Asynchronous function to upload files to the OpenAI API.
-/// Takes the upload option and a vector of file paths to upload each file.
-/// Uploads are skipped if no upload option is provided.
-/// Returns an error if the upload fails.
-pub async fn upload_files_to_openai(
+/// Options selecting the target files and how they are split into output files,
+/// shared by a normal run and the `stats` subcommand.
+#[derive(clap::Args, Debug)]
+struct TargetArgs {
+    /// Paths to the repositories' root directories.
+    /// Each path can be prefixed with an alias (`ALIAS=PATH`) that is prepended to its file paths in the output.
+    /// Required unless `--files-from` is used. `stats` defaults to the current directory.
+    #[clap(value_parser, num_args = 1..)]
+    path_to_repo: Vec<String>,
+
+    #[clap(flatten)]
+    ignore: IgnoreArgs,
+
+    /// Maximum size of each output file in megabytes.
+    /// Defaults to the smallest file size limit of the upload destinations, or to 540.
+    #[clap(short = 's', long = "max-size", value_parser = clap::value_parser!(u64).range(1..100001))]
+    max_file_size: Option<u64>,
+
+    /// Do not detect and redact secrets such as API keys and private keys
+    #[clap(long = "no-redact")]
+    no_redact: bool,
+
+    /// Abort instead of redacting when a secret is detected
+    #[clap(long = "fail-on-secrets", conflicts_with = "no_redact")]
+    fail_on_secrets: bool,
+
+    /// Also redact long random-looking strings that match no other rule.
+    /// Hashes, base64 data and minified code may be redacted too.
+    #[clap(long = "redact-high-entropy", conflicts_with = "no_redact")]
+    redact_high_entropy: bool,
+
+    /// Additional regular expressions of secrets to redact, as `NAME=REGEX` or `REGEX`.
+    /// If the expression has a capture group, only the captured text is redacted.
+    #[clap(long = "secret-pattern", value_parser)]
+    secret_patterns: Vec<String>,
+}
+impl TargetArgs {
+    /// Returns a builder with the roots, ignore rules and secret redaction of the options,
+    /// and the given maximum output file size in bytes.
+    fn builder(&self, max_file_size: u64) -> Result<RepconBuilder, RepconError> {
+        let mut builder = Repcon::builder()
+            .max_file_size(max_file_size)
+            .repconignore_path(&self.ignore.repconignore_path);
+        for root in &self.path_to_repo {
+            builder = builder.input_root(InputRoot::parse(root));
+        }
+        for pattern in &self.ignore.ignore_patterns {
+            builder = builder.ignore_pattern(pattern);
+        }
+        Ok(builder.secret_redactor(if self.no_redact {
+            None
+        } else {
+            Some(self.secret_redactor()?)
+        }))
+    }
+
+    /// Creates the secret redactor from the options,
+    /// adding the custom patterns given with `--secret-pattern`.
+    fn secret_redactor(&self) -> Result<SecretRedactor, RepconError> {
+        let mut redactor = SecretRedactor::new();
+        redactor.fail_on_secrets = self.fail_on_secrets;
+        redactor.detect_high_entropy = self.redact_high_entropy;
+        for spec in &self.secret_patterns {
+            let (name, pattern) = match spec.split_once('=') {
+                Some((name, pattern))
+                    if !name.is_empty() && !name.contains(char::is_whitespace) =>
+                {
+                    (name, pattern)
+                }
+                _ => ("custom", spec.as_str()),
+            };
+            redactor.add_pattern(name, pattern)?;
+        }
+        Ok(redactor)
+    }
+}
+
+/// Ignore rules applied in addition to the `.gitignore` files.
+#[derive(clap::Args, Debug)]
+struct IgnoreArgs {
+    /// Custom ignore patterns
+    #[clap(short = 'i', long = "ignore", value_parser)]
+    ignore_patterns: Vec<String>,
+
+    /// Path to the repconignore file
+    #[clap(
+        short = 'r',
+        long = "repconignore",
+        value_parser,
+        default_value = ".repconignore"
+    )]
+    repconignore_path: String,
+}
+
+/// Connection settings of the OpenAI API.
+#[derive(clap::Args, Debug)]
+struct OpenAiArgs {
+    /// Base URL of the OpenAI API, for Azure OpenAI, proxies or OpenAI-compatible servers
+    #[clap(long = "api-base", env = "OPENAI_BASE_URL", default_value = OPENAI_API_BASE)]
+    api_base: String,
+
+    /// OpenAI organization ID sent with each request
+    #[clap(long = "openai-organization", env = "OPENAI_ORG_ID")]
+    openai_organization: Option<String>,
+
+    /// OpenAI project ID sent with each request
+    #[clap(long = "openai-project", env = "OPENAI_PROJECT_ID")]
+    openai_project: Option<String>,
+}
+impl OpenAiArgs {
+    /// Returns the connection settings with the given API key and retry policy.
+    fn config(&self, api_key: &str, retry_policy: RetryPolicy) -> OpenAiConfig {
+        OpenAiConfig {
+            api_base: self.api_base.clone(),
+            organization: self.openai_organization.clone(),
+            project: self.openai_project.clone(),
+            retry_policy,
+            ..OpenAiConfig::new(api_key)
+        }
+    }
+}
+
+/// Values of `--sort`, see [`FileOrder`].
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum SortOrder {
+    /// Sort by path.
+    Path,
+    /// Sort by path, listing the contents of subdirectories before the files of a directory.
+    DirFirst,
+    /// Sort by file size, smallest first.
+    Size,
+    /// Group files by language, then sort by path.
+    Language,
+    /// Sort by the date of the last git commit touching each file, most recent first.
+    Git,
+    /// Sort files matching the `--priority` patterns first.
+    Priority,
+    /// Place source files right before the modules they declare or import.
+    Dependency,
+}
+impl From<SortOrder> for FileOrder {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Path => Self::Path,
+            SortOrder::DirFirst => Self::DirFirst,
+            SortOrder::Size => Self::Size,
+            SortOrder::Language => Self::Language,
+            SortOrder::Git => Self::Git,
+            SortOrder::Priority => Self::Priority,
+            SortOrder::Dependency => Self::Dependency,
+        }
+    }
+}
+
+/// Values of `--bundle`, see [`BundleFormat`].
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum BundleArg {
+    Zip,
+    #[value(name = "tar.gz")]
+    TarGz,
+}
+impl From<BundleArg> for BundleFormat {
+    fn from(format: BundleArg) -> Self {
+        match format {
+            BundleArg::Zip => Self::Zip,
+            BundleArg::TarGz => Self::TarGz,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect and clean up the files uploaded to OpenAI
+    #[clap(subcommand)]
+    Files(FilesCommand),
+    /// Explain why a file is included in or excluded from the output
+    Explain {
+        /// Path of the file or directory to explain
+        path: PathBuf,
+
+        /// Root directory of the repository the path belongs to
+        #[clap(long = "root", default_value = ".")]
+        root: PathBuf,
+
+        #[clap(flatten)]
+        ignore: IgnoreArgs,
+    },
+    /// Report file counts, sizes, lines and estimated tokens per language and directory
+    Stats {
+        #[clap(flatten)]
+        target: TargetArgs,
+
+        /// Number of largest files to list
+        #[clap(long = "top", default_value_t = 10)]
+        top: usize,
+
+        /// Print the report as JSON
+        #[clap(long = "json")]
+        json: bool,
+    },
+    /// Restore the packed files from a directory of output files or a bundle
+    Unpack {
+        /// Output directory, or `.zip` or `.tar.gz` bundle, to restore the files from
+        input: PathBuf,
+
+        /// Directory to restore the files into
+        #[clap(short = 'o', long = "output", default_value = "unpacked")]
+        output_directory: PathBuf,
+
+        /// Base name of the output files, as given when packing
+        #[clap(short = 'n', long = "output-name", default_value = "output")]
+        output_name: String,
+    },
+}
+
+impl Command {
+    /// Returns the name the subcommand is invoked with.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Files(_) => "files",
+            Self::Explain { .. } => "explain",
+            Self::Stats { .. } => "stats",
+            Self::Unpack { .. } => "unpack",
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum FilesCommand {
+    /// List the uploaded output files
+    List {
+        /// List all uploaded files, not only the output files
+        #[clap(long = "all", conflicts_with_all = ["output_name", "sync_state"])]
+        all: bool,
+
+        /// Base name of the output files to list
+        #[clap(short = 'n', long = "output-name", default_value = "output")]
+        output_name: String,
+
+        /// Only list the files recorded in this sync state file
+        #[clap(long = "sync-state")]
+        sync_state: Option<String>,
+
+        #[clap(flatten)]
+        connection: FilesConnectionArgs,
+    },
+    /// Delete uploaded files by ID
+    Delete {
+        /// IDs of the files to delete
+        #[clap(required = true)]
+        file_ids: Vec<String>,
+
+        /// Only print the files that would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        #[clap(flatten)]
+        connection: FilesConnectionArgs,
+    },
+    /// Delete the superseded files recorded in a sync state file that are still pending deletion
+    Prune {
+        /// Path to the sync state file recording the files to delete
+        #[clap(long = "sync-state", default_value = DEFAULT_SYNC_STATE_FILE)]
+        sync_state: String,
+
+        /// Only print the files that would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        #[clap(flatten)]
+        connection: FilesConnectionArgs,
+    },
+}
+
+/// Connection settings of the `files` subcommands.
+#[derive(clap::Args, Debug)]
+struct FilesConnectionArgs {
+    /// The OpenAI API key
+    #[clap(long = "api-key", env = "OPENAI_API_KEY", hide_env_values = true)]
+    api_key: String,
+
+    #[clap(flatten)]
+    openai: OpenAiArgs,
+}
+impl FilesConnectionArgs {
+    /// Creates the client used by the `files` subcommands.
+    fn client(&self) -> Result<OpenAiClient, RepconError> {
+        OpenAiClient::new(self.openai.config(&self.api_key, RetryPolicy::default()))
+            .map(|client| client.with_retry_notifier(Arc::new(print_retry)))
+    }
+}
+
+/// Prints a warning on standard error before a request to OpenAI is retried.
+fn print_retry(error: &OpenAiError, delay: Duration, attempt: u32, max_attempts: u32) {
+    eprintln!(
+        "{}. Retrying in {:.1}s (attempt {} of {})",
+        error,
+        delay.as_secs_f64(),
+        attempt,
+        max_attempts
+    );
+}
+
+/// Reads the explicit file list given with `--files-from`.
+/// `-` reads the list from standard input.
+fn read_files_from(source: &str) -> Result<Vec<PathBuf>, RepconError> {
+    if source == "-" {
+        read_file_list(io::stdin().lock())
+    } else {
+        read_file_list(fs::File::open(source)?)
+    }
+}
+
+/// Creates the OpenAI client used for uploads from the upload option.
+/// The API key is taken from the option, or from the `OPENAI_API_KEY` environment variable
+/// if only `-u` is specified.
+/// Returns `None` if no upload option is provided.
+fn create_openai_client(
     upload_option: &Option<Option<String>>,
-    files: Vec<PathBuf>,
-) -> io::Result<()> {
+    config: OpenAiConfig,
+) -> Result<Option<OpenAiClient>, RepconError> {
     let api_key = match upload_option {
         Some(Some(key)) => key.clone(),
         Some(None) => env::var("OPENAI_API_KEY").map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                "API key not specified and not found in environment.",
-            )
+            RepconError::Config("API key not specified and not found in environment.".to_string())
         })?,
-        None => {
-            println!("No upload option provided. Skipping upload.");
-            return Ok(());
+        None => return Ok(None),
+    };
+    OpenAiClient::new(OpenAiConfig { api_key, ..config })
+        .map(|client| Some(client.with_retry_notifier(Arc::new(print_retry))))
+}
+
+/// Asynchronous function to upload files to the OpenAI API.
+/// Takes the client and a vector of file paths, uploading up to `concurrency` files in parallel.
+/// Every file is attempted even if earlier uploads fail, and a summary of the
+/// succeeded and failed uploads is printed at the end.
+/// Returns the paths of the uploaded files with their file IDs.
+pub async fn upload_files_to_openai(
+    client: &OpenAiClient,
+    files: Vec<PathBuf>,
+    concurrency: usize,
+) -> Vec<(PathBuf, String)> {
+    let results = upload_files_with(
+        client,
+        &files,
+        concurrency,
+        Some(upload_progress_printer()),
+        print_upload_result,
+    )
+    .await;
+
+    print_upload_summary(&results)
+}
+
+/// Prints the token count and cost estimate of the output files.
+fn print_cost_estimate(estimate: &CostEstimate) {
+    eprintln!("Cost estimate:");
+    for chunk in &estimate.chunks {
+        eprintln!(
+            "  {}: {} tokens ({})",
+            chunk.name,
+            chunk.tokens,
+            format_file_size(chunk.bytes)
+        );
+    }
+    let pricing = &estimate.pricing;
+    eprintln!(
+        "  Embedding: ${:.4} for {} tokens at ${} per 1M tokens",
+        estimate.embedding_cost, estimate.total_tokens, pricing.embedding_per_million_tokens
+    );
+    eprintln!(
+        "  Storage: ${:.4} per day for {} at ${} per GB-day, ${:.4} for {} days",
+        estimate.storage_cost_per_day,
+        format_file_size(estimate.total_bytes),
+        pricing.storage_per_gb_day,
+        estimate.storage_cost,
+        pricing.storage_days
+    );
+    eprintln!("  Total: ${:.4}", estimate.total_cost);
+}
+
+/// Prints the statistics of the `stats` subcommand.
+fn print_stats(stats: &Stats) {
+    println!("{}", stats.total);
+    if stats.skipped_files > 0 {
+        println!(
+            "Skipped non-text or unreadable files: {}",
+            stats.skipped_files
+        );
+    }
+    println!("Projected output files: {}", stats.projected_chunks);
+    println!();
+    println!("Languages:");
+    for group in &stats.languages {
+        println!("  {}", group);
+    }
+    println!();
+    println!("Directories:");
+    for group in &stats.directories {
+        println!("  {}", group);
+    }
+    println!();
+    println!("Largest files:");
+    for file in &stats.largest_files {
+        println!("  {}", file);
+    }
+}
+
+/// Prints what a run would do, for `--dry-run`.
+/// Returns an error if the size limit would be exceeded.
+fn print_plan(plan: &Plan) -> Result<(), RepconError> {
+    println!("Included files ({}):", plan.packed_files.len());
+    for file in &plan.packed_files {
+        let size = fs::metadata(&file.path).map_or(0, |metadata| metadata.len());
+        println!(
+            "  {} ({})",
+            file.display_path.display(),
+            format_file_size(size)
+        );
+    }
+    println!(
+        "Excluded files ({}):",
+        plan.excluded_paths.len() + plan.skipped_files.len()
+    );
+    for excluded in &plan.excluded_paths {
+        let suffix = if excluded.is_dir { "/" } else { "" };
+        println!(
+            "  {}{}: {}",
+            excluded.display_path.display(),
+            suffix,
+            excluded.rule
+        );
+    }
+    for skipped_file in &plan.skipped_files {
+        println!(
+            "  {}: {}",
+            skipped_file.display_path.display(),
+            skipped_file.reason
+        );
+    }
+    for secret in &plan.redacted_secrets {
+        println!(
+            "Would redact secret ({}) in {}",
+            secret.rule, secret.location
+        );
+    }
+
+    println!(
+        "Output files ({} of at most {}):",
+        plan.chunks.len(),
+        plan.max_files
+    );
+    for chunk in &plan.chunks {
+        println!(
+            "  {} ({}, {} tokens)",
+            chunk.name,
+            format_file_size(chunk.size),
+            chunk.tokens.unwrap_or_default()
+        );
+    }
+    println!(
+        "Total: {} of {} allowed, {} tokens",
+        format_file_size(plan.total_size),
+        format_file_size(plan.total_allowed_size),
+        plan.tokens()
+    );
+    if plan.exceeds_max_files() {
+        println!("Warning: More output files would be produced than the maximum number of files.");
+    }
+    if plan.exceeds_size_limit() {
+        return Err(RepconError::SizeLimitExceeded {
+            total_size: plan.total_size,
+            limit: plan.total_allowed_size,
+        });
+    }
+    Ok(())
+}
+
+/// Returns a progress callback that prints a message every 10% of the upload of a large file.
+fn upload_progress_printer() -> UploadProgress {
+    let reported: Mutex<HashMap<PathBuf, u64>> = Mutex::new(HashMap::new());
+    Arc::new(move |file_path, sent, size| {
+        if size < PROGRESS_MIN_FILE_SIZE {
+            return;
+        }
+        let percent = sent * 100 / size / 10 * 10;
+        let mut reported = reported.lock().unwrap();
+        let last = reported.entry(file_path.to_path_buf()).or_insert(0);
+        // A retried upload starts again from zero
+        if percent != *last {
+            *last = percent;
+            if percent > 0 {
+                eprintln!(
+                    "Uploading {}: {}% of {}",
+                    file_path.display(),
+                    percent,
+                    format_file_size(size)
+                );
+            }
+        }
+    })
+}
+
+/// Prints the result of an upload as soon as it is known.
+fn print_upload_result(file_path: &Path, result: &Result<UploadedFile, UploadError>) {
+    match result {
+        Ok(uploaded_file) => println!("Uploaded {} ({})", file_path.display(), uploaded_file.id),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Publishes the output files to each destination given with `--upload-to`,
+/// and adds them to the collection given with `--collection` if the destination supports it.
+/// Returns an error if any upload failed.
+async fn publish_files(
+    args: &Args,
+    destinations: &[UploadDestination],
+    files: &[PathBuf],
+    openai_config: &OpenAiConfig,
+) -> Result<(), RepconError> {
+    for destination in destinations {
+        let openai_client = match destination {
+            // The API key is read from `OPENAI_API_KEY`, as `-u` cannot be combined with it
+            UploadDestination::OpenAi => create_openai_client(&Some(None), openai_config.clone())?,
+            _ => None,
+        };
+        let uploader = destination.uploader(openai_client, &openai_config.retry_policy)?;
+        println!("Uploading to {}", uploader.destination());
+        let results = upload_files_with(
+            uploader.as_ref(),
+            files,
+            args.upload_concurrency as usize,
+            Some(upload_progress_printer()),
+            print_upload_result,
+        )
+        .await;
+        let uploaded_files = print_upload_summary(&results);
+        if uploaded_files.len() < files.len() {
+            return Err(RepconError::UploadsFailed {
+                failed: files.len() - uploaded_files.len(),
+                total: files.len(),
+            });
+        }
+
+        if let Some(collection) = &args.collection {
+            let file_ids: Vec<String> = uploaded_files
+                .into_iter()
+                .map(|(_, file_id)| file_id)
+                .collect();
+            match uploader.attach_to_collection(collection, &file_ids).await {
+                Ok(()) => println!("Added {} files to {}", file_ids.len(), collection),
+                Err(e @ RepconError::Unsupported(_)) => {
+                    eprintln!("{}. Skipping collection {}.", e, collection)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints which files were uploaded and which failed.
+/// Returns the paths of the uploaded files with their file IDs.
+fn print_upload_summary(
+    results: &[(PathBuf, Result<UploadedFile, UploadError>)],
+) -> Vec<(PathBuf, String)> {
+    let failed_count = results.iter().filter(|(_, result)| result.is_err()).count();
+    println!(
+        "Upload summary: {} succeeded, {} failed",
+        results.len() - failed_count,
+        failed_count
+    );
+    let mut uploaded_files = Vec::new();
+    for (file_path, result) in results {
+        match result {
+            Ok(uploaded_file) => {
+                println!("  OK      {} ({})", file_path.display(), uploaded_file.id);
+                uploaded_files.push((file_path.clone(), uploaded_file.id.clone()));
+            }
+            Err(e) => eprintln!("  FAILED  {}: {}", file_path.display(), e.message),
         }
+    }
+    uploaded_files
+}
+
+/// Returns the vector store with the given name or ID, creating it if needed.
+async fn open_vector_store(
+    client: &OpenAiClient,
+    name_or_id: &str,
+) -> Result<VectorStore, RepconError> {
+    let (vector_store, created) = client.find_or_create_vector_store(name_or_id).await?;
+    println!(
+        "{} vector store {} ({})",
+        if created { "Created" } else { "Using" },
+        vector_store.name.as_deref().unwrap_or(name_or_id),
+        vector_store.id
+    );
+    Ok(vector_store)
+}
+
+/// Attaches uploaded files to a vector store and waits until they are indexed.
+/// Takes the paths of the files with their file IDs.
+/// Prints the files that could not be indexed, and returns an error if there are any.
+async fn attach_to_vector_store(
+    client: &OpenAiClient,
+    vector_store: &VectorStore,
+    files: &[(PathBuf, String)],
+) -> Result<(), RepconError> {
+    println!("Indexing {} files...", files.len());
+    let file_ids: Vec<String> = files.iter().map(|(_, file_id)| file_id.clone()).collect();
+    let failures = client
+        .attach_files_to_vector_store(
+            &vector_store.id,
+            &file_ids,
+            VECTOR_STORE_POLL_INTERVAL,
+            DEFAULT_INDEXING_TIMEOUT,
+        )
+        .await?;
+    println!(
+        "Indexing summary: {} succeeded, {} failed",
+        file_ids.len() - failures.len(),
+        failures.len()
+    );
+    for failure in &failures {
+        let file_path = files
+            .iter()
+            .find(|(_, file_id)| *file_id == failure.file_id)
+            .map(|(file_path, _)| file_path.display().to_string())
+            .unwrap_or_else(|| failure.file_id.clone());
+        match &failure.code {
+            Some(code) => eprintln!("  FAILED  {} ({}): {}", file_path, code, failure.message),
+            None => eprintln!("  FAILED  {}: {}", file_path, failure.message),
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(RepconError::IndexingFailed {
+            vector_store_id: vector_store.id.clone(),
+            failed: failures.len(),
+            total: file_ids.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Creates or updates the assistant requested on the command line
+/// so that its `file_search` tool searches the vector store.
+async fn configure_assistant(
+    client: &OpenAiClient,
+    args: &Args,
+    vector_store: &VectorStore,
+) -> Result<(), RepconError> {
+    let settings = AssistantSettings {
+        name: args.create_assistant.clone(),
+        instructions: args.assistant_instructions.clone(),
+        model: args.assistant_model.clone(),
     };
+    let assistant = match (&args.assistant, &args.create_assistant) {
+        (Some(assistant_id), _) => {
+            client
+                .update_assistant(assistant_id, &settings, &vector_store.id)
+                .await
+        }
+        (None, Some(_)) => client.create_assistant(&settings, &vector_store.id).await,
+        (None, None) => return Ok(()),
+    }?;
+    println!(
+        "Assistant {} ({}) now searches vector store {}",
+        assistant.name.as_deref().unwrap_or_default(),
+        assistant.id,
+        vector_store.id
+    );
+    Ok(())
+}
+
+/// Deletes the files superseded by previous syncs from OpenAI,
+/// and from the vector store they were attached to.
+/// Files that could not be deleted are kept in the state and retried by the next sync.
+/// Returns an error if any file could not be deleted.
+async fn delete_superseded_files(
+    client: &OpenAiClient,
+    sync_state: &mut SyncState,
+) -> Result<(), RepconError> {
+    // Files that are already gone do not need to be deleted again
+    let ignore_not_found = |result: Result<(), RepconError>| match result {
+        Err(e) if e.status() == Some(404) => Ok(()),
+        result => result,
+    };
+
+    let file_ids = std::mem::take(&mut sync_state.pending_deletions);
+    let total = file_ids.len();
+    let mut remaining = Vec::new();
+    for file_id in file_ids {
+        let mut result = Ok(());
+        if let Some(vector_store_id) = &sync_state.vector_store_id {
+            result = ignore_not_found(
+                client
+                    .remove_file_from_vector_store(vector_store_id, &file_id)
+                    .await,
+            );
+        }
+        if result.is_ok() {
+            result = ignore_not_found(client.delete_file(&file_id).await);
+        }
+        match result {
+            Ok(()) => println!("Deleted superseded file {}", file_id),
+            Err(e) => {
+                eprintln!("{}", e);
+                remaining.push(file_id);
+            }
+        }
+    }
+    let failed = remaining.len();
+    sync_state.pending_deletions = remaining;
+    if failed > 0 {
+        return Err(RepconError::DeletionsFailed { failed, total });
+    }
+    Ok(())
+}
+
+/// Runs a `files` subcommand.
+async fn run_files_command(command: FilesCommand) -> Result<(), RepconError> {
+    match command {
+        FilesCommand::List {
+            all,
+            output_name,
+            sync_state,
+            connection,
+        } => {
+            let files = connection.client()?.list_files("assistants").await?;
+            let sync_state = sync_state
+                .map(|path| SyncState::load(Path::new(&path)))
+                .transpose()?;
+            let files: Vec<UploadedFile> = files
+                .into_iter()
+                .filter(|file| {
+                    all || match &sync_state {
+                        Some(sync_state) => {
+                            sync_state
+                                .files
+                                .values()
+                                .any(|synced| synced.file_id == file.id)
+                                || sync_state.pending_deletions.contains(&file.id)
+                        }
+                        None => is_output_file_name(&file.filename, &output_name),
+                    }
+                })
+                .collect();
+            for file in &files {
+                println!(
+                    "{}  {:>10}  {}",
+                    file.id,
+                    format_file_size(file.bytes),
+                    file.filename
+                );
+            }
+            println!("{} files", files.len());
+            Ok(())
+        }
+        FilesCommand::Delete {
+            file_ids,
+            dry_run,
+            connection,
+        } => delete_files(&connection.client()?, &file_ids, dry_run).await,
+        FilesCommand::Prune {
+            sync_state,
+            dry_run,
+            connection,
+        } => {
+            let sync_state_path = Path::new(&sync_state);
+            if !sync_state_path.exists() {
+                return Err(RepconError::InvalidPath {
+                    path: sync_state_path.to_path_buf(),
+                    reason: "Sync state file not found".to_string(),
+                });
+            }
+            // Only files this tool uploaded and superseded are deleted: a file name
+            // does not tell which project or run uploaded a file
+            let mut sync_state = SyncState::load(sync_state_path)?;
+            if sync_state.pending_deletions.is_empty() {
+                println!("No superseded files to delete");
+                return Ok(());
+            }
+            if dry_run {
+                return delete_files(&connection.client()?, &sync_state.pending_deletions, true)
+                    .await;
+            }
+
+            let result = delete_superseded_files(&connection.client()?, &mut sync_state).await;
+            // Files that could not be deleted stay in the state to be retried
+            sync_state.save(sync_state_path)?;
+            result
+        }
+    }
+}
 
-    for file_path in files {
-        match upload_file_to_openai(&api_key, file_path.to_str().unwrap(), "assistants").await {
-            Ok(_) => (),
+/// Deletes uploaded files, or only prints them if `dry_run` is set.
+/// Every file is attempted even if other deletions fail.
+/// Returns an error if any deletion failed.
+async fn delete_files(
+    client: &OpenAiClient,
+    file_ids: &[String],
+    dry_run: bool,
+) -> Result<(), RepconError> {
+    let mut failed_count = 0;
+    for file_id in file_ids {
+        if dry_run {
+            println!("Would delete {}", file_id);
+            continue;
+        }
+        match client.delete_file(file_id).await {
+            Ok(()) => println!("Deleted {}", file_id),
             Err(e) => {
-                let error_message = e.to_string();
-                return Err(io::Error::new(io::ErrorKind::Other, error_message));
+                eprintln!("{}", e);
+                failed_count += 1;
             }
         }
     }
 
+    if failed_count > 0 {
+        return Err(RepconError::DeletionsFailed {
+            failed: failed_count,
+            total: file_ids.len(),
+        });
+    }
     Ok(())
 }
 
+/// Returns the exit code for an error that aborted the run:
+/// 2 for invalid options, paths or input data, 3 if the files cannot be packed as requested,
+/// 4 if an upload or another request to an upload destination failed, and 1 otherwise.
+fn exit_code(error: &RepconError) -> u8 {
+    match error {
+        RepconError::Config(_)
+        | RepconError::InvalidPath { .. }
+        | RepconError::IgnorePattern(_)
+        | RepconError::Encoding(_) => 2,
+        RepconError::SizeLimitExceeded { .. }
+        | RepconError::OutputFileTooSmall { .. }
+        | RepconError::SecretDetected { .. } => 3,
+        RepconError::Upload(_)
+        | RepconError::UploadsFailed { .. }
+        | RepconError::IndexingFailed { .. }
+        | RepconError::DeletionsFailed { .. }
+        | RepconError::Api(_)
+        | RepconError::Request { .. }
+        | RepconError::Unsupported(_) => 4,
+        _ => 1,
+    }
+}
+
 /// Main entry point for the `repcon` tool.
-/// Parses command-line arguments and processes files within the specified repository.
-/// Handles file aggregation, size limit checks, output directory creation,
-/// and the upload process to the OpenAI API.
+/// Runs the tool and turns the error that aborted it, if any, into a message and an exit code.
 #[tokio::main]
-async fn main() -> io::Result<()> {
+async fn main() -> ExitCode {
     dotenv().ok();
-    let args = Args::parse();
-    let root_path = Path::new(&args.path_to_repo);
-    let files = collect_target_files(
-        root_path,
-        &args.ignore_patterns,
-        args.repconignore_path.as_ref(),
-    )?;
-    let total_size = get_dir_size(root_path, &files)?;
-
-    // Convert max file size from megabytes to bytes
-    let max_file_size_bytes = (args.max_file_size as u64) * 1024 * 1024;
-
-    // Calculate the total allowed size based on max files and max file size
-    let total_allowed_size = max_file_size_bytes * (args.max_files as u64);
-
-    println!("Total size: {}", format_file_size(total_size));
-    println!(
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Processes files within the specified repositories.
+/// Handles file aggregation, size limit checks, output directory creation,
+/// and the upload process to the OpenAI API.
+async fn run(args: Args) -> Result<(), RepconError> {
+    // A subcommand name takes precedence over a repository directory of the same name
+    if let Some(command) = &args.command {
+        if Path::new(command.name()).is_dir() {
+            eprintln!(
+                "Note: `{0}` runs the {0} subcommand. To pack the directory `{0}`, run `repcon ./{0}`.",
+                command.name()
+            );
+        }
+    }
+    match args.command {
+        Some(Command::Files(command)) => return run_files_command(command).await,
+        Some(Command::Explain { path, root, ignore }) => {
+            let explanation = explain_path(
+                &InputRoot::new(root, None),
+                &path,
+                &ignore.ignore_patterns,
+                Some(&ignore.repconignore_path),
+            )?;
+            match explanation {
+                Explanation::Included => println!("{} is included", path.display()),
+                Explanation::Excluded(rule) => {
+                    println!("{} is excluded by {}", path.display(), rule)
+                }
+                Explanation::Skipped(reason) => {
+                    println!("{} is skipped: {}", path.display(), reason)
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::Stats { target, top, json }) => {
+            let max_file_size = target
+                .max_file_size
+                .map_or(DEFAULT_MAX_FILE_SIZE, |size| size * 1024 * 1024);
+            // Without roots, the builder uses the current directory
+            let stats = target.builder(max_file_size)?.build()?.stats(top)?;
+            if json {
+                let json = serde_json::to_string_pretty(&stats)
+                    .map_err(|e| RepconError::Encoding(e.to_string()))?;
+                println!("{}", json);
+            } else {
+                print_stats(&stats);
+            }
+            return Ok(());
+        }
+        Some(Command::Unpack {
+            input,
+            output_directory,
+            output_name,
+        }) => {
+            let report = unpack(&input, &output_name, &output_directory)?;
+            for path in &report.skipped_files {
+                eprintln!("Not restoring skipped file: {}", path.display());
+            }
+            println!(
+                "Restored {} files from {} output files into {}",
+                report.files.len(),
+                report.chunk_count,
+                output_directory.display()
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+    if args.target.path_to_repo.is_empty() && args.files_from.is_none() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "Give the paths to the repositories, or a file list with --files-from",
+            )
+            .exit();
+    }
+    // Use the smallest file size limit of the upload destinations unless a size is given
+    let destinations = args
+        .upload_to
+        .iter()
+        .map(|spec| UploadDestination::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    // These APIs index the text of the files, which an archive is not
+    if let Some((spec, _)) = args
+        .upload_to
+        .iter()
+        .zip(&destinations)
+        .find(|(_, destination)| {
+            args.bundle.is_some()
+                && matches!(
+                    destination,
+                    UploadDestination::OpenAi
+                        | UploadDestination::Anthropic
+                        | UploadDestination::Gemini
+                )
+        })
+    {
+        return Err(RepconError::Config(format!(
+            "--bundle cannot be used with `--upload-to {}`, which needs the output files as text",
+            spec
+        )));
+    }
+    if args.upload.is_some() && destinations.contains(&UploadDestination::OpenAi) {
+        return Err(RepconError::Config(
+            "-u already uploads the files to OpenAI. Remove `--upload-to openai`, \
+             or use it without -u and with --collection instead of --vector-store."
+                .to_string(),
+        ));
+    }
+    let upload_limit = destinations
+        .iter()
+        .chain(args.upload.is_some().then_some(&UploadDestination::OpenAi))
+        .filter_map(UploadDestination::max_file_size)
+        .min();
+    let max_file_size_bytes = match (args.target.max_file_size, upload_limit) {
+        // Convert max file size from megabytes to bytes
+        (Some(max_file_size), _) => max_file_size * 1024 * 1024,
+        (None, Some(upload_limit)) => upload_limit,
+        (None, None) => DEFAULT_MAX_FILE_SIZE,
+    };
+    // A destination that lowers the default also lowers the total allowed size, so say why
+    if args.target.max_file_size.is_none()
+        && upload_limit.is_some_and(|limit| limit < DEFAULT_MAX_FILE_SIZE)
+    {
+        eprintln!(
+            "Maximum file size defaults to {}, the smallest file size limit of the upload \
+             destinations. Set -s to change it.",
+            format_file_size(max_file_size_bytes)
+        );
+    }
+    if let Some(upload_limit) = upload_limit.filter(|limit| max_file_size_bytes > *limit) {
+        eprintln!(
+            "Warning: The maximum file size ({}) exceeds the upload limit of {}.",
+            format_file_size(max_file_size_bytes),
+            format_file_size(upload_limit)
+        );
+    }
+
+    let mut builder = args
+        .target
+        .builder(max_file_size_bytes)?
+        .max_files(args.max_files)
+        .output_directory(&args.output_directory)
+        .output_name(&args.output_name)
+        .priority_patterns(args.priority_patterns.clone())
+        .count_tokens(args.estimate_cost);
+    // Keep the order of an explicit file list unless an order is requested
+    if let Some(source) = &args.files_from {
+        builder = builder.files(read_files_from(source)?);
+    }
+    if let Some(order) = args.order {
+        builder = builder.order(order.into());
+    }
+    if let Some(format) = args.bundle {
+        builder = builder.bundle(format.into());
+    }
+    let repcon = builder.build()?;
+    let pricing = Pricing {
+        embedding_per_million_tokens: args.embedding_price,
+        storage_per_gb_day: args.storage_price,
+        storage_days: args.storage_days,
+    };
+    if args.dry_run {
+        let plan = repcon.plan()?;
+        let result = print_plan(&plan);
+        if args.estimate_cost {
+            print_cost_estimate(&estimate_cost(&plan.chunks, pricing));
+        }
+        return result;
+    }
+    let files = repcon.collect()?;
+    let total_size = get_target_files_size(&files)?;
+    eprintln!("Total size: {}", format_file_size(total_size));
+    eprintln!(
         "Maximum file size: {} bytes",
         format_file_size(max_file_size_bytes)
     );
-    println!(
+    eprintln!(
         "Total allowed size: {} bytes",
-        format_file_size(total_allowed_size)
+        format_file_size(repcon.total_allowed_size())
     );
 
-    // If total size exceeds the allowed size, throw an error
-    check_size_limits(total_size, total_allowed_size)?;
-
-    // Create the output directory if it doesn't exist
-    fs::create_dir_all(&args.output_directory)?;
+    // Check the size limits, then split the files into chunks, redacting secrets on the way
+    let (mut report, bundle_sink) = if args.stdout {
+        match repcon.pack_into(&files, &mut StdoutSink::new()) {
+            // The reader of the stream, e.g. `head`, does not need more content
+            Err(RepconError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            result => (result?, None),
+        }
+    } else if let (Some(format), Some(bundle_path)) = (args.bundle, repcon.bundle_path()) {
+        // The manifest is added to the bundle once the cost estimate is known
+        fs::create_dir_all(&args.output_directory)?;
+        let mut sink = BundleSink::create(bundle_path, format.into())?;
+        (repcon.pack_into(&files, &mut sink)?, Some(sink))
+    } else {
+        (repcon.pack(&files)?, None)
+    };
+    print_pack_warnings(&report.skipped_files, &report.redacted_secrets);
+    if args.stdout {
+        return Ok(());
+    }
 
-    // Split the files into chunks
-    let generated_files = split_files_into_chunks(
-        &files,
-        Some(root_path),
-        Path::new(&args.output_directory),
-        max_file_size_bytes,
-        &args.output_name,
-    )?;
+    // The tokens are counted while packing with --estimate-cost
+    let cost_estimate = args
+        .estimate_cost
+        .then(|| estimate_cost(&report.chunks, pricing));
+    if let Some(cost_estimate) = &cost_estimate {
+        print_cost_estimate(cost_estimate);
+    }
+    let manifest = Manifest {
+        cost_estimate,
+        ..Manifest::from_report(&report)
+    }
+    .to_json()?;
+    let manifest_name = manifest_file_name(&args.output_name);
+    match bundle_sink {
+        Some(mut sink) => {
+            sink.add_file(&manifest_name, manifest.as_bytes())?;
+            report.output_files = vec![sink.finish()?];
+        }
+        None => fs::write(
+            Path::new(&args.output_directory).join(manifest_name),
+            manifest,
+        )?,
+    }
+    let generated_files = report.output_files;
 
     // Upload to OpenAI
-    upload_files_to_openai(&args.upload, generated_files).await?;
+    let openai_config = args.openai.config(
+        "",
+        RetryPolicy {
+            max_attempts: args.upload_attempts,
+            timeout: Duration::from_secs(args.upload_timeout),
+            ..RetryPolicy::default()
+        },
+    );
+    publish_files(&args, &destinations, &generated_files, &openai_config).await?;
+    let Some(client) = create_openai_client(&args.upload, openai_config)? else {
+        println!("No upload option provided. Skipping upload.");
+        return Ok(());
+    };
 
-    Ok(())
+    // In sync mode, only upload the files that changed since the last sync
+    let sync_state_path = Path::new(&args.sync_state);
+    let mut sync_state = if args.sync {
+        Some(SyncState::load(sync_state_path)?)
+    } else {
+        None
+    };
+    let (unchanged_files, files_to_upload) = match &mut sync_state {
+        Some(sync_state) => {
+            let plan = sync_state.plan(&generated_files)?;
+            println!(
+                "Sync: {} unchanged, {} new or changed",
+                plan.unchanged.len(),
+                plan.changed.len()
+            );
+            (plan.unchanged, plan.changed)
+        }
+        None => (Vec::new(), generated_files),
+    };
+
+    let upload_count = files_to_upload.len();
+    let uploaded_files =
+        upload_files_to_openai(&client, files_to_upload, args.upload_concurrency as usize).await;
+    if let Some(sync_state) = &mut sync_state {
+        for (file_path, file_id) in &uploaded_files {
+            sync_state.record_upload(file_path, file_id)?;
+        }
+        sync_state.save(sync_state_path)?;
+    }
+    if uploaded_files.len() < upload_count {
+        return Err(RepconError::UploadsFailed {
+            failed: upload_count - uploaded_files.len(),
+            total: upload_count,
+        });
+    }
+
+    let mut deletion_result = Ok(());
+    if let Some(name_or_id) = &args.vector_store {
+        let vector_store = open_vector_store(&client, name_or_id).await?;
+        let mut files_to_attach = uploaded_files;
+        // Unchanged files only need to be attached if a previous attach did not succeed,
+        // or they were synced to another vector store
+        if let Some(sync_state) = &sync_state {
+            files_to_attach.extend(
+                unchanged_files
+                    .into_iter()
+                    .filter(|(file_path, _)| !sync_state.is_attached(file_path, &vector_store.id)),
+            );
+        }
+        attach_to_vector_store(&client, &vector_store, &files_to_attach).await?;
+        // Superseded copies are only deleted once their replacements are attached
+        if let Some(sync_state) = &mut sync_state {
+            let attached_files: Vec<PathBuf> = files_to_attach
+                .into_iter()
+                .map(|(file_path, _)| file_path)
+                .collect();
+            deletion_result = delete_superseded_files(&client, sync_state).await;
+            sync_state.record_attach(&attached_files, &vector_store.id);
+            sync_state.save(sync_state_path)?;
+        }
+        configure_assistant(&client, &args, &vector_store).await?;
+    } else if let Some(sync_state) = &mut sync_state {
+        deletion_result = delete_superseded_files(&client, sync_state).await;
+        sync_state.forget_vector_store();
+    }
+
+    // Files that could not be deleted stay in the state to be retried by the next sync
+    if let Some(sync_state) = &sync_state {
+        sync_state.save(sync_state_path)?;
+    }
+    deletion_result
 }
@@ -0,0 +1,130 @@
+use crate::{CostEstimate, OutputChunk, RedactedSecret, RepconError, RunReport};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Returns the name of the manifest of the output files named after `output_name`,
+/// i.e. `{output_name}_manifest.json`.
+pub fn manifest_file_name(output_name: &str) -> String {
+    format!("{}_manifest.json", output_name)
+}
+
+/// A target file that was left out of the output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestSkippedFile {
+    /// Path shown for the file in the output.
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Describes a set of output files: the chunks, and the files that were packed,
+/// skipped and redacted. It is written next to the output files or into the bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of repcon that packed the files.
+    pub repcon_version: String,
+    /// The output chunks, in order.
+    pub chunks: Vec<OutputChunk>,
+    /// Paths shown for the packed files in the output, in the order they were packed in.
+    pub files: Vec<PathBuf>,
+    #[serde(default)]
+    pub skipped_files: Vec<ManifestSkippedFile>,
+    #[serde(default)]
+    pub redacted_secrets: Vec<RedactedSecret>,
+    /// Total size of the packed target files in bytes.
+    pub total_size: u64,
+    /// Total size of the output chunks in bytes.
+    pub output_size: u64,
+    /// The estimated cost of indexing the output chunks, if their tokens were counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_estimate: Option<CostEstimate>,
+}
+impl Manifest {
+    /// Describes the output of a run, without a cost estimate.
+    pub fn from_report(report: &RunReport) -> Self {
+        Self {
+            repcon_version: env!("CARGO_PKG_VERSION").to_string(),
+            chunks: report.chunks.clone(),
+            files: report
+                .packed_files
+                .iter()
+                .map(|file| file.display_path.clone())
+                .collect(),
+            skipped_files: report
+                .skipped_files
+                .iter()
+                .map(|file| ManifestSkippedFile {
+                    path: file.display_path.clone(),
+                    reason: file.reason.to_string(),
+                })
+                .collect(),
+            redacted_secrets: report.redacted_secrets.clone(),
+            total_size: report.total_size,
+            output_size: report.output_size,
+            cost_estimate: None,
+        }
+    }
+
+    /// Parses a manifest from JSON.
+    pub fn from_json(json: &str) -> Result<Self, RepconError> {
+        serde_json::from_str(json)
+            .map_err(|e| RepconError::Encoding(format!("Invalid manifest: {}", e)))
+    }
+
+    /// Returns the manifest as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, RepconError> {
+        serde_json::to_string_pretty(self).map_err(|e| RepconError::Encoding(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use super::*;
+    use crate::{estimate_cost, Pricing, SkipReason, SkippedFile, TargetFile};
+    use std::path::Path;
+
+    #[test]
+    fn test_manifest_from_report() -> Result<(), RepconError> {
+        let root = Path::new("/repo");
+        let report = RunReport {
+            packed_files: vec![TargetFile::new(root.join("src/main.rs"), Some(root))],
+            skipped_files: vec![SkippedFile {
+                path: root.join("image.png"),
+                display_path: PathBuf::from("image.png"),
+                reason: SkipReason::NotText,
+            }],
+            chunks: vec![OutputChunk {
+                name: "output_1.txt".to_string(),
+                size: 120,
+                tokens: Some(30),
+            }],
+            chunk_count: 1,
+            total_size: 40,
+            output_size: 120,
+            ..RunReport::default()
+        };
+
+        let manifest = Manifest::from_report(&report);
+        assert_eq!(manifest.files, vec![PathBuf::from("src/main.rs")]);
+        assert_eq!(manifest.skipped_files[0].path, PathBuf::from("image.png"));
+        assert_eq!(manifest.skipped_files[0].reason, "not a text file");
+        assert_eq!(Manifest::from_json(&manifest.to_json()?)?, manifest);
+        assert!(!manifest.to_json()?.contains("cost_estimate"));
+
+        let manifest = Manifest {
+            cost_estimate: Some(estimate_cost(&report.chunks, Pricing::default())),
+            ..manifest
+        };
+        let json = manifest.to_json()?;
+        let parsed = Manifest::from_json(&json)?;
+        assert_eq!(
+            parsed.cost_estimate.map(|estimate| estimate.total_tokens),
+            Some(30)
+        );
+        assert!(matches!(
+            Manifest::from_json("{}"),
+            Err(RepconError::Encoding(_))
+        ));
+        assert_eq!(manifest_file_name("pack"), "pack_manifest.json");
+        Ok(())
+    }
+}
@@ -1,12 +1,583 @@
-use reqwest::{self, multipart};
-use std::path::Path;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use crate::uploader::{file_body, UploadProgress};
+use crate::RepconError;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{self, multipart, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A file object returned by the OpenAI Files API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UploadedFile {
+    pub id: String,
+    pub filename: String,
+    #[serde(default)]
+    pub bytes: u64,
+    /// Unix timestamp of the upload.
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+/// A page of objects returned by a list endpoint.
+#[derive(Deserialize)]
+pub(crate) struct ListPage<T> {
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub last_id: Option<String>,
+}
+
+/// An error returned by the OpenAI API, or a failure to send a request to it.
+///
+/// Carries the HTTP status and the OpenAI error code when the API rejected the request,
+/// or only a message when the request could not be sent at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenAiError {
+    /// What was being done, e.g. `create vector store docs`.
+    pub action: String,
+    pub status: Option<u16>,
+    pub code: Option<String>,
+    pub message: String,
+}
+impl OpenAiError {
+    /// Constructs an `OpenAiError` for a failure that happened before a response was received.
+    pub(crate) fn new(action: &str, message: impl ToString) -> Self {
+        Self {
+            action: action.to_string(),
+            status: None,
+            code: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Constructs an `OpenAiError` from a non-successful response status and body.
+    /// The OpenAI error message and code are extracted from the body if it is a JSON error object.
+    pub(crate) fn from_response(action: &str, status: u16, body: &str) -> Self {
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            error: ErrorDetail,
+        }
+        #[derive(Deserialize)]
+        struct ErrorDetail {
+            message: Option<String>,
+            code: Option<String>,
+        }
+
+        let (message, code) = match serde_json::from_str::<ErrorBody>(body) {
+            Ok(ErrorBody { error }) => (error.message.unwrap_or_default(), error.code),
+            Err(_) => (body.trim().to_string(), None),
+        };
+        Self {
+            action: action.to_string(),
+            status: Some(status),
+            code,
+            message,
+        }
+    }
+}
+impl fmt::Display for OpenAiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to {}", self.action)?;
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {}", status)?;
+            if let Some(code) = &self.code {
+                write!(f, ", {}", code)?;
+            }
+            write!(f, ")")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+impl std::error::Error for OpenAiError {}
+
+/// An error that occurred while uploading a file to OpenAI.
+///
+/// Carries the HTTP status and the OpenAI error code when the API rejected the request,
+/// or only a message when the request could not be sent at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadError {
+    pub file_path: PathBuf,
+    pub status: Option<u16>,
+    pub code: Option<String>,
+    pub message: String,
+}
+impl UploadError {
+    /// Constructs an `UploadError` for a failure that happened before a response was received.
+    pub(crate) fn new(file_path: &Path, message: impl ToString) -> Self {
+        Self {
+            file_path: file_path.to_path_buf(),
+            status: None,
+            code: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Constructs an `UploadError` from the error of the upload request.
+    fn from_error(file_path: &Path, error: OpenAiError) -> Self {
+        Self {
+            file_path: file_path.to_path_buf(),
+            status: error.status,
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to upload {}", self.file_path.display())?;
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {}", status)?;
+            if let Some(code) = &self.code {
+                write!(f, ", {}", code)?;
+            }
+            write!(f, ")")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+impl std::error::Error for UploadError {}
+
+/// Header opting in to the Assistants API, which includes vector stores.
+const OPENAI_BETA_HEADER: &str = "OpenAI-Beta";
+const ASSISTANTS_V2: &str = "assistants=v2";
+
+/// Maximum size of a file uploaded to the OpenAI Files API, in bytes.
+pub const OPENAI_MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Maximum number of files returned by a single request of `list_files`.
+const FILES_LIST_LIMIT: usize = 10000;
+
+/// Default base URL of the OpenAI API.
+pub const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+/// How failed requests to OpenAI are retried.
+///
+/// Requests failing with a rate limit (429), a timeout (408), a server error (5xx)
+/// or a connection error are retried with exponential backoff and jitter,
+/// waiting as long as the `Retry-After` header asks if the response has one,
+/// but never longer than `max_backoff`. Requests that create objects, such as uploaded files,
+/// vector stores or assistants, are only retried after a connection error, a rate limit (429)
+/// or a 502 or 503 response, which show that the server did not process them. A timeout,
+/// a 500 or a 504 fails them right away, as the object may have been created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts per request, including the first one.
+    pub max_attempts: u32,
+    /// Backoff before the first retry. Doubles with each retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff between two attempts.
+    pub max_backoff: Duration,
+    /// Timeout of a single attempt.
+    pub timeout: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+impl RetryPolicy {
+    /// Returns the backoff before the given retry (1 for the first retry).
+    /// Half of the delay is randomized so that parallel clients do not retry in lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let delay = exponential.min(self.max_backoff);
+        delay / 2 + delay.mul_f64(rand::thread_rng().gen_range(0.0..0.5))
+    }
+
+    /// Returns the delay before the given retry: the delay requested by the server,
+    /// if any, capped at `max_backoff`, or the exponential backoff otherwise.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(self.max_backoff),
+            None => self.backoff(retry),
+        }
+    }
+}
+
+/// Connection settings for the OpenAI API or an OpenAI-compatible endpoint.
+#[derive(Clone, PartialEq, Eq)]
+pub struct OpenAiConfig {
+    pub api_key: String,
+    /// Base URL of the API, e.g. `https://api.openai.com/v1`.
+    /// Azure OpenAI endpoints (`*.openai.azure.com`) are authenticated with the `api-key` header.
+    pub api_base: String,
+    /// Sent as the `OpenAI-Organization` header if set.
+    pub organization: Option<String>,
+    /// Sent as the `OpenAI-Project` header if set.
+    pub project: Option<String>,
+    pub retry_policy: RetryPolicy,
+}
+impl OpenAiConfig {
+    /// Constructs a new `OpenAiConfig` for the official OpenAI API with the default retry policy.
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            api_base: OPENAI_API_BASE.to_string(),
+            organization: None,
+            project: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Returns true if the API base points to an Azure OpenAI resource.
+    fn is_azure(&self) -> bool {
+        reqwest::Url::parse(&self.api_base)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .is_some_and(|host| {
+                host.ends_with(".openai.azure.com")
+                    || host.ends_with(".cognitiveservices.azure.com")
+            })
+    }
+}
+
+/// Receives each failed attempt of a request that is about to be retried: the error,
+/// the delay before the next attempt, the number of the next attempt
+/// and the maximum number of attempts.
+pub type RetryNotifier = Arc<dyn Fn(&OpenAiError, Duration, u32, u32) + Send + Sync>;
+
+/// A client for the OpenAI API.
+pub struct OpenAiClient {
+    http: reqwest::Client,
+    api_base: String,
+    retry_policy: RetryPolicy,
+    on_retry: Option<RetryNotifier>,
+}
+
+/// Which failures of a request are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Retry {
+    /// Every transient failure is retried, as repeating the request is harmless.
+    Always,
+    /// Only failures that show the server did not act on the request are retried:
+    /// connection errors, rate limits and 502/503 responses. Used for requests that would
+    /// create a duplicate if they were repeated after a timeout, e.g. uploading a file
+    /// or creating a vector store.
+    Unprocessed,
+}
+
+impl Retry {
+    /// Returns true if a request that failed before a response was received is retried.
+    pub(crate) fn retries_error(self, error: &reqwest::Error) -> bool {
+        match self {
+            Retry::Always => error.is_timeout() || error.is_connect() || error.is_request(),
+            Retry::Unprocessed => error.is_connect(),
+        }
+    }
+
+    /// Returns true if a request that failed with the given response status is retried.
+    pub(crate) fn retries_status(self, status: u16) -> bool {
+        match self {
+            Retry::Always => matches!(status, 408 | 429 | 500..=599),
+            Retry::Unprocessed => matches!(status, 429 | 502 | 503),
+        }
+    }
+}
+
+/// A failed attempt of a request, and whether it is worth retrying.
+struct FailedAttempt {
+    error: OpenAiError,
+    retryable: bool,
+    retry_after: Option<Duration>,
+}
+
+impl OpenAiClient {
+    /// Constructs a new `OpenAiClient` from the provided configuration.
+    /// Returns an error if the API key, organization or project cannot be sent as a header.
+    pub fn new(config: OpenAiConfig) -> Result<Self, RepconError> {
+        let header_value = |name: &str, value: &str| {
+            let mut header_value = HeaderValue::from_str(value).map_err(|_| {
+                RepconError::Config(format!("Invalid characters in the OpenAI {}", name))
+            })?;
+            header_value.set_sensitive(true);
+            Ok::<_, RepconError>(header_value)
+        };
+
+        let mut headers = HeaderMap::new();
+        if config.is_azure() {
+            headers.insert("api-key", header_value("API key", &config.api_key)?);
+        } else {
+            let authorization = format!("Bearer {}", config.api_key);
+            headers.insert(AUTHORIZATION, header_value("API key", &authorization)?);
+        }
+        if let Some(organization) = &config.organization {
+            headers.insert(
+                "OpenAI-Organization",
+                header_value("organization", organization)?,
+            );
+        }
+        if let Some(project) = &config.project {
+            headers.insert("OpenAI-Project", header_value("project", project)?);
+        }
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(config.retry_policy.timeout)
+            .build()
+            .map_err(|e| RepconError::Config(e.to_string()))?;
+        Ok(Self {
+            http,
+            api_base: config.api_base.trim_end_matches('/').to_string(),
+            retry_policy: config.retry_policy,
+            on_retry: None,
+        })
+    }
+
+    /// Calls `notifier` before each retry, e.g. to print a warning.
+    /// Retries are silent otherwise.
+    pub fn with_retry_notifier(mut self, notifier: RetryNotifier) -> Self {
+        self.on_retry = Some(notifier);
+        self
+    }
+
+    /// Asynchronously uploads a file to OpenAI, retrying according to the retry policy.
+    ///
+    /// This function creates a multipart form with the file and purpose, and sends it to
+    /// the OpenAI API for uploading. The file is streamed into the request rather than
+    /// read into memory first.
+    ///
+    /// # Arguments
+    /// * `file_path` - The path of the file to be uploaded.
+    /// * `purpose` - The purpose of the file upload, typically related to its intended use.
+    ///
+    /// # Returns
+    /// The uploaded file object, or an `UploadError` if the file could not be read,
+    /// or the last attempt failed.
+    pub async fn upload_file(
+        &self,
+        file_path: &Path,
+        purpose: &str,
+    ) -> Result<UploadedFile, UploadError> {
+        self.upload_file_with_progress(file_path, purpose, None)
+            .await
+    }
+
+    /// Uploads a file like [`OpenAiClient::upload_file`], reporting the bytes sent to `progress`.
+    pub async fn upload_file_with_progress(
+        &self,
+        file_path: &Path,
+        purpose: &str,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        let action = &format!("upload {}", file_path.display());
+        let url = &self.url("files");
+        // A timed out upload may have created the file, and retrying it would leave
+        // an orphaned copy behind that the sync state does not record
+        let body = self
+            .send(action, Retry::Unprocessed, || async move {
+                // The file is opened again for every attempt
+                let (contents, size) = file_body(file_path, progress)
+                    .await
+                    .map_err(|e| OpenAiError::new(action, e))?;
+                let file_part = multipart::Part::stream_with_length(contents, size)
+                    .file_name(file_path.to_string_lossy().into_owned())
+                    .mime_str("text/plain")
+                    .expect("text/plain is a valid MIME type");
+
+                // Create the multipart Form
+                let form = multipart::Form::new()
+                    .part("file", file_part)
+                    .text("purpose", purpose.to_string());
+                Ok(self.http.post(url).multipart(form))
+            })
+            .await
+            .map_err(|e| UploadError::from_error(file_path, e))?;
+        parse_response(action, &body).map_err(|e| UploadError::from_error(file_path, e))
+    }
+
+    /// Lists the uploaded files with the given purpose, most recent first.
+    pub async fn list_files(&self, purpose: &str) -> Result<Vec<UploadedFile>, RepconError> {
+        let mut files = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut path = format!("files?purpose={}&limit={}", purpose, FILES_LIST_LIMIT);
+            if let Some(after) = &after {
+                path.push_str(&format!("&after={}", after));
+            }
+            let page: ListPage<UploadedFile> = self.get_json("list files", &path).await?;
+            let last_id = page
+                .last_id
+                .or_else(|| page.data.last().map(|file| file.id.clone()));
+            files.extend(page.data);
+            match last_id {
+                Some(last_id) if page.has_more => after = Some(last_id),
+                _ => return Ok(files),
+            }
+        }
+    }
+
+    /// Deletes an uploaded file.
+    pub async fn delete_file(&self, file_id: &str) -> Result<(), RepconError> {
+        self.delete(
+            &format!("delete file {}", file_id),
+            &format!("files/{}", file_id),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the URL of an API endpoint, e.g. `files`.
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.api_base, path)
+    }
+
+    /// Sends a GET request to an Assistants API endpoint and parses the JSON response.
+    pub(crate) async fn get_json<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        path: &str,
+    ) -> Result<T, OpenAiError> {
+        let url = &self.url(path);
+        let body = self
+            .send(action, Retry::Always, || async move {
+                Ok(self.http.get(url).header(OPENAI_BETA_HEADER, ASSISTANTS_V2))
+            })
+            .await?;
+        parse_response(action, &body)
+    }
+
+    /// Sends a POST request with a JSON body to an Assistants API endpoint
+    /// and parses the JSON response.
+    /// As the request may create an object, it is only retried if it was not processed.
+    pub(crate) async fn post_json<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        path: &str,
+        payload: &serde_json::Value,
+    ) -> Result<T, OpenAiError> {
+        let url = &self.url(path);
+        let body = self
+            .send(action, Retry::Unprocessed, || async move {
+                Ok(self
+                    .http
+                    .post(url)
+                    .header(OPENAI_BETA_HEADER, ASSISTANTS_V2)
+                    .json(payload))
+            })
+            .await?;
+        parse_response(action, &body)
+    }
+
+    /// Sends a DELETE request to an Assistants API endpoint.
+    pub(crate) async fn delete(&self, action: &str, path: &str) -> Result<(), OpenAiError> {
+        let url = &self.url(path);
+        self.send(action, Retry::Always, || async move {
+            Ok(self
+                .http
+                .delete(url)
+                .header(OPENAI_BETA_HEADER, ASSISTANTS_V2))
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// Sends a request built by `build_request`, retrying according to the retry policy
+    /// the failures selected by `retry`.
+    /// The request is rebuilt for every attempt, and an error building it is not retried.
+    /// Returns the body of the successful response, or the error of the last attempt.
+    pub(crate) async fn send<F, Fut>(
+        &self,
+        action: &str,
+        retry: Retry,
+        build_request: F,
+    ) -> Result<String, OpenAiError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<RequestBuilder, OpenAiError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let failure = match self.try_send(action, retry, build_request().await?).await {
+                Ok(body) => return Ok(body),
+                Err(failure) => failure,
+            };
+            if !failure.retryable || attempt >= self.retry_policy.max_attempts {
+                return Err(failure.error);
+            }
+
+            let delay = self.retry_policy.delay(attempt, failure.retry_after);
+            if let Some(on_retry) = &self.on_retry {
+                on_retry(
+                    &failure.error,
+                    delay,
+                    attempt + 1,
+                    self.retry_policy.max_attempts,
+                );
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Makes a single attempt of a request.
+    async fn try_send(
+        &self,
+        action: &str,
+        retry: Retry,
+        request: RequestBuilder,
+    ) -> Result<String, FailedAttempt> {
+        // Send the request
+        let response = request.send().await.map_err(|e| FailedAttempt {
+            retryable: retry.retries_error(&e),
+            error: OpenAiError::new(action, e),
+            retry_after: None,
+        })?;
+
+        let status = response.status();
+        let retry_after = parse_retry_after(response.headers());
+        let body = response.text().await.map_err(|e| FailedAttempt {
+            retryable: retry == Retry::Always,
+            error: OpenAiError::new(action, e),
+            retry_after: None,
+        })?;
+        if !status.is_success() {
+            let error = OpenAiError::from_response(action, status.as_u16(), &body);
+            // Running out of quota is reported as a rate limit, but waiting does not help
+            let retryable = retry.retries_status(status.as_u16())
+                && error.code.as_deref() != Some("insufficient_quota");
+            return Err(FailedAttempt {
+                error,
+                retryable,
+                retry_after,
+            });
+        }
+        Ok(body)
+    }
+}
+
+/// Parses the JSON body of a successful response.
+fn parse_response<T: DeserializeOwned>(action: &str, body: &str) -> Result<T, OpenAiError> {
+    serde_json::from_str(body)
+        .map_err(|e| OpenAiError::new(action, format!("Unexpected response from OpenAI: {}", e)))
+}
+
+/// Reads the delay requested by the `retry-after-ms` or `Retry-After` response headers.
+/// Only delays given in (milli)seconds are supported.
+pub(crate) fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+    header("retry-after-ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| header("retry-after"))
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
 
 /// Asynchronously uploads a file to OpenAI using the provided API key.
 ///
-/// This function reads the file asynchronously, creates a multipart form with the file and purpose,
-/// and sends it to the OpenAI API for uploading.
+/// Creates a client with the default retry policy and uploads the file with it.
+/// See [`OpenAiClient::upload_file`].
 ///
 /// # Arguments
 /// * `api_key` - The API key for authentication with OpenAI.
@@ -14,43 +585,349 @@ use tokio::io::AsyncReadExt;
 /// * `purpose` - The purpose of the file upload, typically related to its intended use.
 ///
 /// # Returns
-/// Result indicating success or failure.
+/// The uploaded file object, or an `UploadError` if the file could not be read,
+/// the request could not be sent, or OpenAI responded with a non-successful status.
 pub async fn upload_file_to_openai(
     api_key: &str,
     file_path: &str,
     purpose: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-
-    // Asynchronously read the file contents
+) -> Result<UploadedFile, UploadError> {
     let path = Path::new(file_path);
-    let mut file = File::open(path).await?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents).await?;
-
-    // Create a file Part from the contents
-    let file_part = multipart::Part::bytes(contents)
-        .file_name(file_path.to_string())
-        .mime_str("text/plain")?;
-
-    // Create the multipart Form
-    let form = multipart::Form::new()
-        .part("file", file_part)
-        .text("purpose", purpose.to_string());
-
-    // Send the request
-    let response = client
-        .post("https://api.openai.com/v1/files")
-        .bearer_auth(api_key)
-        .multipart(form)
-        .send()
-        .await?;
+    let client =
+        OpenAiClient::new(OpenAiConfig::new(api_key)).map_err(|e| UploadError::new(path, e))?;
+    client.upload_file(path, purpose).await
+}
+
+#[cfg(test)]
+mod openai_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer, RecordedRequest};
+    use crate::upload_files_with;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    const FILE_RESPONSE: &str = r#"{"id": "file-abc123", "object": "file", "bytes": 5, "filename": "output_1.txt", "purpose": "assistants"}"#;
+
+    fn test_client(server: &MockServer, max_attempts: u32) -> OpenAiClient {
+        test_client_with_timeout(server, max_attempts, Duration::from_secs(10))
+    }
 
-    if response.status().is_success() {
-        println!("File uploaded successfully!");
-    } else {
-        println!("Failed to upload file: {:?}", response.text().await?);
+    fn test_client_with_timeout(
+        server: &MockServer,
+        max_attempts: u32,
+        timeout: Duration,
+    ) -> OpenAiClient {
+        let retry_policy = RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            timeout,
+        };
+        let config = OpenAiConfig {
+            api_base: server.url.clone(),
+            organization: Some("org-test".to_string()),
+            retry_policy,
+            ..OpenAiConfig::new("test-key")
+        };
+        OpenAiClient::new(config).unwrap()
     }
 
-    Ok(())
+    fn create_output_file(dir: &Path) -> PathBuf {
+        let file_path = dir.join("output_1.txt");
+        std::fs::write(&file_path, "hello").unwrap();
+        file_path
+    }
+
+    #[tokio::test]
+    async fn test_upload_retries_rate_limits_and_server_errors() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(
+                429,
+                r#"{"error": {"message": "Rate limit reached", "code": "rate_limit_exceeded"}}"#,
+            )
+            .with_header("retry-after-ms", "20"),
+            MockResponse::new(503, "Service Unavailable"),
+            MockResponse::new(200, FILE_RESPONSE),
+        ])
+        .await;
+        let dir = tempdir().unwrap();
+        let file_path = create_output_file(dir.path());
+
+        let retries = Arc::new(Mutex::new(Vec::new()));
+        let notified = retries.clone();
+        let uploaded_file = test_client(&server, 5)
+            .with_retry_notifier(Arc::new(move |_, _, attempt, max_attempts| {
+                notified.lock().unwrap().push((attempt, max_attempts));
+            }))
+            .upload_file(&file_path, "assistants")
+            .await
+            .unwrap();
+
+        assert_eq!(uploaded_file.id, "file-abc123");
+        assert_eq!(*retries.lock().unwrap(), vec![(2, 5), (3, 5)]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/files");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer test-key"));
+        assert_eq!(requests[0].header("openai-organization"), Some("org-test"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_does_not_retry_client_errors() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(
+                400,
+                r#"{"error": {"message": "Invalid purpose", "code": "invalid_purpose"}}"#,
+            ),
+            MockResponse::new(200, FILE_RESPONSE),
+        ])
+        .await;
+        let dir = tempdir().unwrap();
+        let file_path = create_output_file(dir.path());
+
+        let error = test_client(&server, 5)
+            .upload_file(&file_path, "assistants")
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.status, Some(400));
+        assert_eq!(error.code.as_deref(), Some("invalid_purpose"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_upload_gives_up_after_max_attempts() {
+        let server = MockServer::start(|_| MockResponse::new(502, "Bad Gateway")).await;
+        let dir = tempdir().unwrap();
+        let file_path = create_output_file(dir.path());
+
+        let error = test_client(&server, 3)
+            .upload_file(&file_path, "assistants")
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.status, Some(502));
+        assert_eq!(error.file_path, file_path);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_post_is_only_retried_if_not_processed() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(503, "Service Unavailable"),
+            MockResponse::new(500, "Internal Server Error"),
+            MockResponse::new(200, r#"{"id": "vs_1"}"#),
+        ])
+        .await;
+        let client = test_client(&server, 5);
+
+        let error = client
+            .post_json::<serde_json::Value>(
+                "create vector store",
+                "vector_stores",
+                &serde_json::json!({}),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, Some(500));
+        assert_eq!(server.requests().len(), 2);
+
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(500, "Internal Server Error"),
+            MockResponse::new(200, r#"{"id": "vs_1"}"#),
+        ])
+        .await;
+        let client = test_client(&server, 5);
+        let vector_store: serde_json::Value = client
+            .get_json("get vector store", "vector_stores/vs_1")
+            .await
+            .unwrap();
+        assert_eq!(vector_store["id"], "vs_1");
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_requests() {
+        let stall = |_: &RecordedRequest| {
+            MockResponse::new(200, FILE_RESPONSE).with_delay(Duration::from_secs(5))
+        };
+        let timeout = Duration::from_millis(200);
+
+        // Reads are retried after a timeout, up to max_attempts
+        let server = MockServer::start(stall).await;
+        let retries = Arc::new(Mutex::new(Vec::new()));
+        let notified = retries.clone();
+        let error = test_client_with_timeout(&server, 3, timeout)
+            .with_retry_notifier(Arc::new(move |_, _, attempt, max_attempts| {
+                notified.lock().unwrap().push((attempt, max_attempts));
+            }))
+            .get_json::<serde_json::Value>("get vector store", "vector_stores/vs_1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, None);
+        assert!(error.message.contains("timed out"));
+        assert_eq!(*retries.lock().unwrap(), vec![(2, 3), (3, 3)]);
+        assert_eq!(server.requests().len(), 3);
+
+        // Uploads may have created the file, so they are not retried
+        let server = MockServer::start(stall).await;
+        let dir = tempdir().unwrap();
+        let file_path = create_output_file(dir.path());
+        let error = test_client_with_timeout(&server, 3, timeout)
+            .upload_file(&file_path, "assistants")
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, None);
+        assert!(error.message.contains("timed out"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_upload_files_concurrently_in_order() {
+        let server = MockServer::start(|request| {
+            let body = String::from_utf8_lossy(&request.body);
+            if body.contains("fail me") {
+                MockResponse::new(400, r#"{"error": {"message": "Bad file", "code": null}}"#)
+            } else {
+                MockResponse::new(200, FILE_RESPONSE)
+            }
+        })
+        .await;
+        let dir = tempdir().unwrap();
+        let file_paths: Vec<PathBuf> = (1..=5)
+            .map(|i| {
+                let file_path = dir.path().join(format!("output_{}.txt", i));
+                let content = if i == 3 { "fail me" } else { "hello" };
+                std::fs::write(&file_path, content).unwrap();
+                file_path
+            })
+            .collect();
+
+        let mut reported = Vec::new();
+        let client = test_client(&server, 1);
+        let results = upload_files_with(&client, &file_paths, 3, None, |file_path, result| {
+            reported.push((file_path.to_path_buf(), result.is_ok()))
+        })
+        .await;
+
+        let expected: Vec<(PathBuf, bool)> = file_paths
+            .iter()
+            .enumerate()
+            .map(|(i, file_path)| (file_path.clone(), i != 2))
+            .collect();
+        assert_eq!(reported, expected);
+        assert_eq!(results.len(), 5);
+        assert!(results[2].1.is_err());
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_upload_streams_file_with_progress() {
+        let server = MockServer::start(|_| MockResponse::new(200, FILE_RESPONSE)).await;
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("output_1.txt");
+        let contents = "0123456789".repeat(10_000);
+        std::fs::write(&file_path, &contents).unwrap();
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let progress: UploadProgress = {
+            let sent = Arc::clone(&sent);
+            Arc::new(move |_, bytes, size| sent.lock().unwrap().push((bytes, size)))
+        };
+        test_client(&server, 1)
+            .upload_file_with_progress(&file_path, "assistants", Some(&progress))
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert!(sent.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(sent.last(), Some(&(100_000, 100_000)));
+        let requests = server.requests();
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains(&contents));
+        assert!(body.contains("name=\"purpose\""));
+    }
+
+    #[tokio::test]
+    async fn test_list_files_follows_pages() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(
+                200,
+                r#"{"data": [{"id": "file-2", "filename": "output_2.txt", "bytes": 7, "created_at": 1700000001}], "has_more": true}"#,
+            ),
+            MockResponse::new(
+                200,
+                r#"{"data": [{"id": "file-1", "filename": "output_1.txt", "bytes": 5, "created_at": 1700000000}], "has_more": false}"#,
+            ),
+        ])
+        .await;
+
+        let files = test_client(&server, 1)
+            .list_files("assistants")
+            .await
+            .unwrap();
+
+        let file_ids: Vec<&str> = files.iter().map(|file| file.id.as_str()).collect();
+        assert_eq!(file_ids, vec!["file-2", "file-1"]);
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/files?purpose=assistants&limit=10000");
+        assert_eq!(
+            requests[1].path,
+            "/files?purpose=assistants&limit=10000&after=file-2"
+        );
+    }
+
+    #[test]
+    fn test_openai_config_is_azure() {
+        let config = OpenAiConfig {
+            api_base: "https://my-resource.openai.azure.com/openai/v1/".to_string(),
+            ..OpenAiConfig::new("test-key")
+        };
+        assert!(config.is_azure());
+        assert!(!OpenAiConfig::new("test-key").is_azure());
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let retry_policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..RetryPolicy::default()
+        };
+        for (retry, expected) in [(1, 1), (2, 2), (3, 4), (4, 8), (5, 10), (30, 10)] {
+            let backoff = retry_policy.backoff(retry);
+            let expected = Duration::from_secs(expected);
+            assert!(backoff >= expected / 2 && backoff <= expected);
+        }
+
+        let retry_after = Some(Duration::from_secs(3));
+        assert_eq!(retry_policy.delay(1, retry_after), Duration::from_secs(3));
+        let retry_after = Some(Duration::from_secs(86400));
+        assert_eq!(retry_policy.delay(1, retry_after), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_error_from_response() {
+        let body = r#"{"error": {"message": "Invalid purpose", "type": "invalid_request_error", "param": null, "code": "invalid_purpose"}}"#;
+        let error = UploadError::from_error(
+            Path::new("output/output_1.txt"),
+            OpenAiError::from_response("upload output/output_1.txt", 400, body),
+        );
+
+        assert_eq!(error.status, Some(400));
+        assert_eq!(error.code.as_deref(), Some("invalid_purpose"));
+        assert_eq!(error.message, "Invalid purpose");
+        assert_eq!(
+            error.to_string(),
+            "Failed to upload output/output_1.txt (HTTP 400, invalid_purpose): Invalid purpose"
+        );
+
+        let error = OpenAiError::from_response("list vector stores", 502, "Bad Gateway\n");
+        assert_eq!(error.code, None);
+        assert_eq!(error.message, "Bad Gateway");
+        assert_eq!(
+            error.to_string(),
+            "Failed to list vector stores (HTTP 502): Bad Gateway"
+        );
+    }
 }
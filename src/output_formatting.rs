@@ -31,8 +31,8 @@ impl PageFormat {
         };
         let header = PageFormat::create_page_header(&file_path, 1);
         let footer = PageFormat::create_page_footer(&file_path);
-        let header_size = header.as_bytes().len() as u64;
-        let footer_size = footer.as_bytes().len() as u64;
+        let header_size = header.len() as u64;
+        let footer_size = footer.len() as u64;
         Self {
             header,
             footer,
@@ -78,12 +78,12 @@ impl PageFormat {
 
     /// Returns the size of the current page header.
     pub fn get_page_header_size(&self) -> u64 {
-        self.header.as_bytes().len() as u64
+        self.header.len() as u64
     }
 
     /// Returns the size of the current page footer.
     pub fn get_page_footer_size(&self) -> u64 {
-        self.footer.as_bytes().len() as u64
+        self.footer.len() as u64
     }
 
     /// Increments the page number and updates the header and footer.
@@ -0,0 +1,653 @@
+use crate::{
+    check_size_limits, collect_excluded_paths, collect_target_files_from_roots, compute_stats,
+    get_target_files_size, manifest_file_name, pack_target_files, sort_target_files,
+    target_files_from_list, BundleFormat, BundleSink, ChunkSink, DirectorySink, ExcludedPath,
+    FileOrder, InputRoot, Manifest, OutputChunk, PackResult, PackedFile, RedactedSecret,
+    RepconError, SecretRedactor, SkippedFile, Stats, TargetFile, TokenCounter,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of output files unless another one is given.
+pub const DEFAULT_MAX_FILES: u64 = 20;
+
+/// Maximum size of each output file in bytes unless another one is given.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 540 * 1024 * 1024;
+
+/// Packs the files of one or more repositories into output files.
+///
+/// This is the library counterpart of the `repcon` command: it collects the target files,
+/// orders them, checks the size limits and splits them into output files.
+///
+/// ```no_run
+/// use repcon::Repcon;
+///
+/// let report = Repcon::builder()
+///     .root("./my-repo")
+///     .ignore_pattern("*.lock")
+///     .max_file_size(1024 * 1024)
+///     .output_directory("./packed")
+///     .build()?
+///     .run()?;
+/// println!("{} output files", report.output_files.len());
+/// # Ok::<(), repcon::RepconError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Repcon {
+    roots: Vec<InputRoot>,
+    file_list: Option<Vec<PathBuf>>,
+    ignore_patterns: Vec<String>,
+    repconignore_path: Option<String>,
+    order: Option<FileOrder>,
+    priority_patterns: Vec<String>,
+    max_file_size: u64,
+    max_files: u64,
+    output_directory: PathBuf,
+    output_name: String,
+    bundle: Option<BundleFormat>,
+    secret_redactor: Option<SecretRedactor>,
+    count_tokens: bool,
+}
+
+/// Builds a [`Repcon`]. See [`Repcon::builder`].
+///
+/// The output goes to the output directory, as loose files or as a bundle.
+/// Other sinks are not part of the settings but are passed to [`Repcon::run_into`]
+/// and [`Repcon::pack_into`]: a run borrows its sink mutably and some sinks must be
+/// finished afterwards, e.g. [`BundleSink::finish`], while a `Repcon` can be run several times.
+#[derive(Debug, Clone)]
+pub struct RepconBuilder {
+    repcon: Repcon,
+}
+
+/// What a run of [`Repcon`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunReport {
+    /// Target files that were packed, with the paths shown for them in the output.
+    pub packed_files: Vec<TargetFile>,
+    /// Target files that were left out of the output, with the reason.
+    pub skipped_files: Vec<SkippedFile>,
+    /// Secrets that were replaced with placeholders, with their location.
+    pub redacted_secrets: Vec<RedactedSecret>,
+    /// Paths of the generated output files. Empty when packing into a sink that does not
+    /// write to files.
+    pub output_files: Vec<PathBuf>,
+    /// Number of output chunks.
+    pub chunk_count: u64,
+    /// The output chunks, in the order they were written.
+    pub chunks: Vec<OutputChunk>,
+    /// Total size of the target files in bytes.
+    pub total_size: u64,
+    /// Total size of the output chunks in bytes.
+    pub output_size: u64,
+}
+
+/// What a run of [`Repcon`] would do, computed without writing any file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Target files that would be packed.
+    pub packed_files: Vec<TargetFile>,
+    /// Files and directories excluded by an ignore rule. Empty for an explicit file list.
+    pub excluded_paths: Vec<ExcludedPath>,
+    /// Target files that would be left out of the output, with the reason.
+    pub skipped_files: Vec<SkippedFile>,
+    /// Secrets that would be replaced with placeholders, with their location.
+    pub redacted_secrets: Vec<RedactedSecret>,
+    /// Output files that would be produced, with their number of tokens.
+    pub chunks: Vec<OutputChunk>,
+    /// Total size of the target files in bytes.
+    pub total_size: u64,
+    /// Maximum total size of the target files in bytes.
+    pub total_allowed_size: u64,
+    /// Maximum number of output files.
+    pub max_files: u64,
+}
+impl Plan {
+    /// Returns true if the total size of the target files exceeds the allowed size,
+    /// in which case the run fails.
+    pub fn exceeds_size_limit(&self) -> bool {
+        self.total_size > self.total_allowed_size
+    }
+
+    /// Returns true if more output files would be produced than the maximum number of files.
+    pub fn exceeds_max_files(&self) -> bool {
+        self.chunks.len() as u64 > self.max_files
+    }
+
+    /// Returns the total size of the output files in bytes.
+    pub fn output_size(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.size).sum()
+    }
+
+    /// Returns the number of tokens of the output files.
+    pub fn tokens(&self) -> u64 {
+        self.chunks.iter().filter_map(|chunk| chunk.tokens).sum()
+    }
+}
+
+/// Discards the output chunks, to plan a run without writing them.
+struct PlanningSink;
+
+impl ChunkSink for PlanningSink {
+    fn begin_chunk(&mut self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_all(&mut self, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_chunk(&mut self) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+impl Repcon {
+    /// Starts building a `Repcon`.
+    ///
+    /// By default, files are sorted by path, known kinds of secrets are redacted
+    /// (without high-entropy detection), and at most
+    /// [`DEFAULT_MAX_FILES`] files of [`DEFAULT_MAX_FILE_SIZE`] bytes are written
+    /// to `output/output_N.txt`.
+    pub fn builder() -> RepconBuilder {
+        RepconBuilder {
+            repcon: Repcon {
+                roots: Vec::new(),
+                file_list: None,
+                ignore_patterns: Vec::new(),
+                repconignore_path: None,
+                order: Some(FileOrder::default()),
+                priority_patterns: Vec::new(),
+                max_file_size: DEFAULT_MAX_FILE_SIZE,
+                max_files: DEFAULT_MAX_FILES,
+                output_directory: PathBuf::from("output"),
+                output_name: "output".to_string(),
+                bundle: None,
+                secret_redactor: Some(SecretRedactor::new()),
+                count_tokens: false,
+            },
+        }
+    }
+
+    /// Returns the maximum size of each output file in bytes.
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Returns the maximum total size of the target files in bytes.
+    pub fn total_allowed_size(&self) -> u64 {
+        self.max_file_size.saturating_mul(self.max_files)
+    }
+
+    /// Returns the directory the output files are written to.
+    pub fn output_directory(&self) -> &Path {
+        &self.output_directory
+    }
+
+    /// Returns the path of the bundle written instead of the output files, if one is set.
+    pub fn bundle_path(&self) -> Option<PathBuf> {
+        self.bundle.map(|format| {
+            self.output_directory
+                .join(format!("{}.{}", self.output_name, format.extension()))
+        })
+    }
+
+    /// Collects the target files and packs them into the output directory.
+    /// Same as [`Repcon::collect`] followed by [`Repcon::pack`].
+    pub fn run(&self) -> Result<RunReport, RepconError> {
+        let files = self.collect()?;
+        self.pack(&files)
+    }
+
+    /// Collects the target files and packs them into a sink instead of the output directory.
+    /// Same as [`Repcon::collect`] followed by [`Repcon::pack_into`].
+    pub fn run_into(&self, sink: &mut dyn ChunkSink) -> Result<RunReport, RepconError> {
+        let files = self.collect()?;
+        self.pack_into(&files, sink)
+    }
+
+    /// Plans a run without writing any file: collects the target files, lists the excluded
+    /// files with the rule that excluded them, and splits the target files into chunks to
+    /// compute the output files and their tokens. Exceeding the size limits is reported
+    /// instead of failing.
+    pub fn plan(&self) -> Result<Plan, RepconError> {
+        let files = self.collect()?;
+        let mut excluded_paths = Vec::new();
+        if self.file_list.is_none() {
+            for root in &self.roots {
+                excluded_paths.extend(collect_excluded_paths(
+                    root,
+                    &self.ignore_patterns,
+                    self.repconignore_path.as_ref(),
+                )?);
+            }
+        }
+
+        let result = self.plan_chunks(&files)?;
+        Ok(Plan {
+            total_size: get_target_files_size(&files)?,
+            packed_files: packed_target_files(&files, &result.packed_files),
+            excluded_paths,
+            skipped_files: result.skipped_files,
+            redacted_secrets: result.redacted_secrets,
+            chunks: result.chunks,
+            total_allowed_size: self.total_allowed_size(),
+            max_files: self.max_files,
+        })
+    }
+
+    /// Computes the statistics of the target files, listing the `largest_count` largest files.
+    /// The files are read once, packing them without writing any file.
+    pub fn stats(&self, largest_count: usize) -> Result<Stats, RepconError> {
+        let files = self.collect()?;
+        let result = self.plan_chunks(&files)?;
+        compute_stats(&files, &result, largest_count)
+    }
+
+    /// Splits the target files into chunks without writing them, counting their tokens.
+    fn plan_chunks(&self, files: &[TargetFile]) -> Result<PackResult, RepconError> {
+        pack_target_files(
+            files,
+            &mut PlanningSink,
+            self.max_file_size,
+            &self.output_name,
+            self.secret_redactor.as_ref(),
+            Some(&TokenCounter::new()?),
+        )
+    }
+
+    /// Collects the target files, in the order they are packed in.
+    pub fn collect(&self) -> Result<Vec<TargetFile>, RepconError> {
+        let mut files = match &self.file_list {
+            Some(paths) => target_files_from_list(paths.clone(), &self.roots),
+            None => collect_target_files_from_roots(
+                &self.roots,
+                &self.ignore_patterns,
+                self.repconignore_path.as_ref(),
+            )?,
+        };
+        if let Some(order) = self.order {
+            sort_target_files(&mut files, order, &self.priority_patterns)?;
+        }
+        Ok(files)
+    }
+
+    /// Checks the size limits and packs the target files into the output directory,
+    /// creating it if needed.
+    ///
+    /// If a bundle format is set, the output files and their manifest are written into a single
+    /// archive, `{output_name}.zip` or `{output_name}.tar.gz`, which is the only output file
+    /// of the report.
+    pub fn pack(&self, files: &[TargetFile]) -> Result<RunReport, RepconError> {
+        let total_size = get_target_files_size(files)?;
+        check_size_limits(total_size, self.total_allowed_size())?;
+
+        fs::create_dir_all(&self.output_directory)?;
+        match (self.bundle, self.bundle_path()) {
+            (Some(format), Some(bundle_path)) => {
+                let mut sink = BundleSink::create(bundle_path, format)?;
+                let mut report = self.pack_unchecked(files, total_size, &mut sink)?;
+                let manifest = Manifest::from_report(&report).to_json()?;
+                sink.add_file(&manifest_file_name(&self.output_name), manifest.as_bytes())?;
+                report.output_files = vec![sink.finish()?];
+                Ok(report)
+            }
+            _ => self.pack_unchecked(
+                files,
+                total_size,
+                &mut DirectorySink::new(&self.output_directory),
+            ),
+        }
+    }
+
+    /// Checks the size limits and packs the target files into a sink. The output chunks are
+    /// named like the output files, but the output directory is not used.
+    pub fn pack_into(
+        &self,
+        files: &[TargetFile],
+        sink: &mut dyn ChunkSink,
+    ) -> Result<RunReport, RepconError> {
+        let total_size = get_target_files_size(files)?;
+        check_size_limits(total_size, self.total_allowed_size())?;
+        self.pack_unchecked(files, total_size, sink)
+    }
+
+    fn pack_unchecked(
+        &self,
+        files: &[TargetFile],
+        total_size: u64,
+        sink: &mut dyn ChunkSink,
+    ) -> Result<RunReport, RepconError> {
+        let token_counter = self.count_tokens.then(TokenCounter::new).transpose()?;
+        let result = pack_target_files(
+            files,
+            sink,
+            self.max_file_size,
+            &self.output_name,
+            self.secret_redactor.as_ref(),
+            token_counter.as_ref(),
+        )?;
+
+        Ok(RunReport {
+            packed_files: packed_target_files(files, &result.packed_files),
+            skipped_files: result.skipped_files,
+            redacted_secrets: result.redacted_secrets,
+            output_files: result.output_files,
+            chunk_count: result.chunk_count,
+            chunks: result.chunks,
+            total_size,
+            output_size: result.output_size,
+        })
+    }
+}
+
+/// Returns the target files that were packed, in the order of `files`.
+fn packed_target_files(files: &[TargetFile], packed_files: &[PackedFile]) -> Vec<TargetFile> {
+    let packed_paths: HashSet<&Path> = packed_files
+        .iter()
+        .map(|file| file.path.as_path())
+        .collect();
+    files
+        .iter()
+        .filter(|file| packed_paths.contains(file.path.as_path()))
+        .cloned()
+        .collect()
+}
+
+impl RepconBuilder {
+    /// Adds a root directory to collect the target files from.
+    pub fn root(self, path: impl Into<PathBuf>) -> Self {
+        self.input_root(InputRoot::new(path, None))
+    }
+
+    /// Adds a root directory whose files are shown prefixed with an alias in the output.
+    pub fn root_with_alias(self, path: impl Into<PathBuf>, alias: &str) -> Self {
+        self.input_root(InputRoot::new(path, Some(alias.to_string())))
+    }
+
+    /// Adds a root directory.
+    pub fn input_root(mut self, root: InputRoot) -> Self {
+        self.repcon.roots.push(root);
+        self
+    }
+
+    /// Packs an explicit list of files instead of walking the roots.
+    /// The roots are only used to compute the paths shown in the output.
+    /// The files are kept in the given order unless an order is set afterwards.
+    pub fn files(mut self, paths: Vec<PathBuf>) -> Self {
+        self.repcon.file_list = Some(paths);
+        self.repcon.order = None;
+        self
+    }
+
+    /// Adds an ignore pattern, in `.gitignore` syntax.
+    pub fn ignore_pattern(mut self, pattern: &str) -> Self {
+        self.repcon.ignore_patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Sets an ignore file applied to every root, in addition to the
+    /// `.repconignore` file in each root.
+    pub fn repconignore_path(mut self, path: &str) -> Self {
+        self.repcon.repconignore_path = Some(path.to_string());
+        self
+    }
+
+    /// Sets the order of the files in the output.
+    pub fn order(mut self, order: FileOrder) -> Self {
+        self.repcon.order = Some(order);
+        self
+    }
+
+    /// Sets the patterns of the files put first by `FileOrder::Priority`.
+    pub fn priority_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.repcon.priority_patterns = patterns;
+        self
+    }
+
+    /// Sets the maximum size of each output file in bytes.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.repcon.max_file_size = bytes;
+        self
+    }
+
+    /// Sets the maximum number of output files.
+    pub fn max_files(mut self, count: u64) -> Self {
+        self.repcon.max_files = count;
+        self
+    }
+
+    /// Sets the directory the output files are written to.
+    pub fn output_directory(mut self, path: impl Into<PathBuf>) -> Self {
+        self.repcon.output_directory = path.into();
+        self
+    }
+
+    /// Sets the base name of the output files, which are named `{name}_{N}.txt`.
+    pub fn output_name(mut self, name: &str) -> Self {
+        self.repcon.output_name = name.to_string();
+        self
+    }
+
+    /// Writes the output files into a single zip or tar.gz archive in the output directory,
+    /// along with their manifest, instead of writing them as separate files.
+    pub fn bundle(mut self, format: BundleFormat) -> Self {
+        self.repcon.bundle = Some(format);
+        self
+    }
+
+    /// Sets the secret redactor, or disables secret redaction with `None`.
+    pub fn secret_redactor(mut self, secret_redactor: Option<SecretRedactor>) -> Self {
+        self.repcon.secret_redactor = secret_redactor;
+        self
+    }
+
+    /// Counts the tokens of each output chunk and packed file while packing,
+    /// e.g. to estimate the cost of indexing them with [`crate::estimate_cost`].
+    /// [`Repcon::plan`] and [`Repcon::stats`] always count them.
+    pub fn count_tokens(mut self, count_tokens: bool) -> Self {
+        self.repcon.count_tokens = count_tokens;
+        self
+    }
+
+    /// Validates the settings and builds the `Repcon`.
+    /// Without any root, the current directory is used.
+    pub fn build(mut self) -> Result<Repcon, RepconError> {
+        if self.repcon.max_file_size == 0 || self.repcon.max_files == 0 {
+            return Err(RepconError::Config(
+                "The maximum file size and number of files must be positive".to_string(),
+            ));
+        }
+        if self.repcon.output_name.is_empty() || self.repcon.output_name.contains(['/', '\\']) {
+            return Err(RepconError::Config(format!(
+                "Invalid output name: {:?}",
+                self.repcon.output_name
+            )));
+        }
+        if self.repcon.roots.is_empty() {
+            self.repcon.roots.push(InputRoot::new(".", None));
+        }
+        Ok(self.repcon)
+    }
+}
+
+#[cfg(test)]
+mod repcon_tests {
+    use super::*;
+    use crate::{Chunk, ExclusionRule, SkipReason};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run_reports_packed_and_skipped_files() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("README.md"), "# Title\n")?;
+        fs::write(root.join("src/main.rs"), "fn main() {}\n")?;
+        fs::write(root.join("src/image.bin"), [0xff, 0xfe, 0x00])?;
+        fs::write(root.join("notes.log"), "ignored\n")?;
+
+        let report = Repcon::builder()
+            .root_with_alias(&root, "repo")
+            .ignore_pattern("*.log")
+            .output_directory(dir.path().join("packed"))
+            .output_name("pack")
+            .build()?
+            .run()?;
+
+        let packed: Vec<&Path> = report
+            .packed_files
+            .iter()
+            .map(|file| file.display_path.as_path())
+            .collect();
+        assert_eq!(
+            packed,
+            vec![Path::new("repo/README.md"), Path::new("repo/src/main.rs")]
+        );
+        assert_eq!(report.skipped_files.len(), 1);
+        assert_eq!(report.skipped_files[0].path, root.join("src/image.bin"));
+        assert_eq!(report.skipped_files[0].reason, SkipReason::NotText);
+        assert_eq!(
+            report.output_files,
+            vec![dir.path().join("packed/pack_1.txt")]
+        );
+        assert_eq!(report.total_size, 24);
+        assert!(report.output_size > report.total_size);
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_into_memory_sink() -> io::Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("a.txt"), "a\n".repeat(30))?;
+        fs::write(dir.path().join("b.txt"), "b\n")?;
+
+        let repcon = Repcon::builder()
+            .root(dir.path())
+            .max_file_size(200)
+            .output_directory(dir.path().join("packed"))
+            .build()?;
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let report = repcon.run_into(&mut chunks)?;
+
+        assert!(!dir.path().join("packed").exists());
+        assert!(report.output_files.is_empty());
+        assert_eq!(report.chunk_count, chunks.len() as u64);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks[0].name, "output_1.txt");
+        assert_eq!(
+            report.output_size,
+            chunks
+                .iter()
+                .map(|chunk| chunk.content.len() as u64)
+                .sum::<u64>()
+        );
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.content.len() as u64 <= repcon.max_file_size()));
+        let content: String = chunks
+            .iter()
+            .map(|chunk| String::from_utf8_lossy(&chunk.content).into_owned())
+            .collect();
+        assert!(content.contains("b.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_into_bundle() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        fs::create_dir_all(&root)?;
+        fs::write(root.join("a.txt"), "a\n")?;
+
+        let report = Repcon::builder()
+            .root(&root)
+            .output_directory(dir.path().join("packed"))
+            .bundle(BundleFormat::Zip)
+            .build()?
+            .run()?;
+
+        let bundle_path = dir.path().join("packed/output.zip");
+        assert_eq!(report.output_files, vec![bundle_path.clone()]);
+        let archive = zip::ZipArchive::new(fs::File::open(bundle_path)?)?;
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["output_1.txt", "output_manifest.json"]);
+        assert!(!dir.path().join("packed/output_1.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_does_not_write_files() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        fs::create_dir_all(&root)?;
+        fs::write(root.join("main.rs"), "fn main() {}\n".repeat(20))?;
+        fs::write(root.join("image.bin"), [0xff, 0xfe, 0x00])?;
+        fs::write(root.join("debug.log"), "ignored\n")?;
+
+        let plan = Repcon::builder()
+            .root(&root)
+            .ignore_pattern("*.log")
+            .max_file_size(200)
+            .max_files(1)
+            .output_directory(dir.path().join("packed"))
+            .build()?
+            .plan()?;
+
+        assert!(!dir.path().join("packed").exists());
+        assert_eq!(plan.packed_files.len(), 1);
+        assert_eq!(plan.skipped_files.len(), 1);
+        assert_eq!(plan.excluded_paths.len(), 1);
+        assert_eq!(
+            plan.excluded_paths[0].rule,
+            ExclusionRule::IgnorePattern("*.log".to_string())
+        );
+        assert_eq!(plan.total_size, 263);
+        assert!(plan.exceeds_size_limit());
+        assert!(plan.exceeds_max_files());
+        assert_eq!(plan.chunks[0].name, "output_1.txt");
+        assert!(plan.chunks.iter().all(|chunk| chunk.size <= 200));
+        assert!(plan.chunks.iter().all(|chunk| chunk.tokens > Some(0)));
+        assert_eq!(
+            plan.tokens(),
+            plan.chunks
+                .iter()
+                .filter_map(|chunk| chunk.tokens)
+                .sum::<u64>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_and_limits() -> io::Result<()> {
+        assert!(matches!(
+            Repcon::builder().output_name("").build(),
+            Err(RepconError::Config(_))
+        ));
+        assert!(matches!(
+            Repcon::builder().max_files(0).build(),
+            Err(RepconError::Config(_))
+        ));
+
+        let dir = tempdir()?;
+        fs::write(dir.path().join("large.txt"), "x".repeat(100))?;
+        let result = Repcon::builder()
+            .root(dir.path())
+            .max_file_size(40)
+            .max_files(2)
+            .output_directory(dir.path().join("packed"))
+            .build()?
+            .run();
+        assert!(matches!(
+            result,
+            Err(RepconError::SizeLimitExceeded {
+                total_size: 100,
+                limit: 80
+            })
+        ));
+        Ok(())
+    }
+}
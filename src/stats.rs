@@ -0,0 +1,223 @@
+use crate::{detect_language, format_file_size, PackResult, PackedFile, RepconError, TargetFile};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Counts of a group of target files, e.g. the files of a language or a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub lines: u64,
+    pub estimated_tokens: u64,
+}
+impl GroupStats {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    fn add(&mut self, file: &FileStats) {
+        self.files += 1;
+        self.bytes += file.bytes;
+        self.lines += file.lines;
+        self.estimated_tokens += file.estimated_tokens;
+    }
+}
+impl fmt::Display for GroupStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<24} {:>6} files {:>10} {:>9} lines ~{} tokens",
+            self.name,
+            self.files,
+            format_file_size(self.bytes),
+            self.lines,
+            self.estimated_tokens
+        )
+    }
+}
+
+/// Counts of a single target file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileStats {
+    /// Path shown for the file in the output.
+    pub path: PathBuf,
+    pub language: &'static str,
+    pub bytes: u64,
+    pub lines: u64,
+    pub estimated_tokens: u64,
+}
+impl fmt::Display for FileStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<40} {:>10} {:>9} lines ~{} tokens",
+            self.path.display(),
+            format_file_size(self.bytes),
+            self.lines,
+            self.estimated_tokens
+        )
+    }
+}
+
+/// Statistics of the target files that would be packed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub total: GroupStats,
+    /// Groups by language, largest first.
+    pub languages: Vec<GroupStats>,
+    /// Groups by top-level directory of the display paths, largest first.
+    /// Files at the top level are grouped under `.`.
+    pub directories: Vec<GroupStats>,
+    /// The largest files, largest first.
+    pub largest_files: Vec<FileStats>,
+    /// Number of files left out because they are not text or cannot be read.
+    pub skipped_files: u64,
+    /// Number of output files the target files would be split into.
+    pub projected_chunks: u64,
+}
+
+/// Returns the top-level directory of a display path, or `.` for a file at the top level.
+fn top_level_directory(path: &Path) -> String {
+    let mut components = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)));
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    }
+}
+
+/// Sorts groups by size, largest first, and by name for equal sizes.
+fn sorted_groups(groups: HashMap<String, GroupStats>) -> Vec<GroupStats> {
+    let mut groups: Vec<GroupStats> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
+
+/// Computes the statistics of the target files from the result of packing them,
+/// listing the `largest_count` largest files. Tokens are only counted if they were
+/// counted while packing.
+/// Only the packed files are counted; the skipped ones are only counted as skipped.
+pub fn compute_stats(
+    files: &[TargetFile],
+    result: &PackResult,
+    largest_count: usize,
+) -> Result<Stats, RepconError> {
+    let mut stats = Stats {
+        total: GroupStats::new("Total"),
+        skipped_files: result.skipped_files.len() as u64,
+        projected_chunks: result.chunk_count,
+        ..Stats::default()
+    };
+    let mut languages: HashMap<String, GroupStats> = HashMap::new();
+    let mut directories: HashMap<String, GroupStats> = HashMap::new();
+    let mut file_stats = Vec::new();
+
+    let packed_files: HashMap<&Path, &PackedFile> = result
+        .packed_files
+        .iter()
+        .map(|file| (file.path.as_path(), file))
+        .collect();
+    for file in files {
+        let Some(packed_file) = packed_files.get(file.path.as_path()) else {
+            continue;
+        };
+        let file = FileStats {
+            path: file.display_path.clone(),
+            language: detect_language(&file.path),
+            bytes: packed_file.bytes,
+            lines: packed_file.lines,
+            estimated_tokens: packed_file.tokens.unwrap_or_default(),
+        };
+
+        stats.total.add(&file);
+        languages
+            .entry(file.language.to_string())
+            .or_insert_with(|| GroupStats::new(file.language))
+            .add(&file);
+        let directory = top_level_directory(&file.path);
+        directories
+            .entry(directory.clone())
+            .or_insert_with(|| GroupStats::new(&directory))
+            .add(&file);
+        file_stats.push(file);
+    }
+
+    stats.languages = sorted_groups(languages);
+    stats.directories = sorted_groups(directories);
+    file_stats.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    file_stats.truncate(largest_count);
+    stats.largest_files = file_stats;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::{pack_target_files, Chunk, TokenCounter};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compute_stats() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("src/main.rs"), "fn main() {\n}\n")?;
+        fs::write(root.join("src/lib.rs"), "pub mod a;")?;
+        fs::write(root.join("README.md"), "# Title\n\nText\n")?;
+        fs::write(root.join("image.png"), [0x89, 0x50, 0xff])?;
+        let files: Vec<TargetFile> = ["src/main.rs", "src/lib.rs", "README.md", "image.png"]
+            .iter()
+            .map(|path| TargetFile::new(root.join(path), Some(root)))
+            .collect();
+
+        let counter = TokenCounter::new()?;
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let result = pack_target_files(&files, &mut chunks, 1024, "output", None, Some(&counter))?;
+        let stats = compute_stats(&files, &result, 2)?;
+        assert_eq!(
+            stats.total,
+            GroupStats {
+                name: "Total".to_string(),
+                files: 3,
+                bytes: 38,
+                lines: 6,
+                estimated_tokens: 15,
+            }
+        );
+        let languages: Vec<(&str, u64)> = stats
+            .languages
+            .iter()
+            .map(|group| (group.name.as_str(), group.files))
+            .collect();
+        assert_eq!(languages, vec![("Rust", 2), ("Markdown", 1)]);
+        let directories: Vec<(&str, u64)> = stats
+            .directories
+            .iter()
+            .map(|group| (group.name.as_str(), group.bytes))
+            .collect();
+        assert_eq!(directories, vec![("src", 24), (".", 14)]);
+        let largest: Vec<&Path> = stats
+            .largest_files
+            .iter()
+            .map(|file| file.path.as_path())
+            .collect();
+        assert_eq!(
+            largest,
+            vec![Path::new("README.md"), Path::new("src/main.rs")]
+        );
+        assert_eq!(stats.skipped_files, 1);
+        assert_eq!(stats.projected_chunks, 1);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["largest_files"][1]["language"], "Rust");
+        Ok(())
+    }
+}
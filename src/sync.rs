@@ -0,0 +1,238 @@
+use crate::RepconError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Default path of the state file of `--sync`.
+pub const DEFAULT_SYNC_STATE_FILE: &str = ".repcon-sync.json";
+
+/// A generated file that was uploaded by a previous sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedFile {
+    pub file_id: String,
+    /// SHA-256 of the uploaded content, as lowercase hex.
+    pub sha256: String,
+    /// The vector store the file was successfully attached to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_store_id: Option<String>,
+}
+
+/// What was uploaded by previous syncs, stored as JSON in a local state file.
+///
+/// Files are keyed by their file name in the output directory, e.g. `output_1.txt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// The vector store the files were last attached to,
+    /// which superseded files are removed from before they are deleted.
+    #[serde(default)]
+    pub vector_store_id: Option<String>,
+    #[serde(default)]
+    pub files: BTreeMap<String, SyncedFile>,
+    /// IDs of superseded files that still have to be deleted.
+    #[serde(default)]
+    pub pending_deletions: Vec<String>,
+}
+
+/// The files of a sync that can be kept and the files that have to be uploaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Files whose content was already uploaded, with the ID of the uploaded file.
+    pub unchanged: Vec<(PathBuf, String)>,
+    /// New files and files whose content changed since they were uploaded.
+    pub changed: Vec<PathBuf>,
+}
+
+impl SyncState {
+    /// Reads the state file. Returns an empty state if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self, RepconError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_reader(io::BufReader::new(file)).map_err(|e| {
+            RepconError::Encoding(format!("Invalid sync state file {}: {}", path.display(), e))
+        })
+    }
+
+    /// Writes the state file, replacing it atomically.
+    pub fn save(&self, path: &Path) -> Result<(), RepconError> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, json + "\n")?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Compares the generated files with the uploaded ones.
+    ///
+    /// Files that are no longer generated are forgotten, and their uploaded copies
+    /// are scheduled for deletion.
+    pub fn plan(&mut self, generated_files: &[PathBuf]) -> Result<SyncPlan, RepconError> {
+        let mut plan = SyncPlan::default();
+        let mut file_names = Vec::new();
+        for file_path in generated_files {
+            let file_name = sync_file_name(file_path)?;
+            match self.files.get(&file_name) {
+                Some(synced_file) if synced_file.sha256 == file_sha256(file_path)? => plan
+                    .unchanged
+                    .push((file_path.clone(), synced_file.file_id.clone())),
+                _ => plan.changed.push(file_path.clone()),
+            }
+            file_names.push(file_name);
+        }
+
+        let removed: Vec<String> = self
+            .files
+            .keys()
+            .filter(|file_name| !file_names.contains(file_name))
+            .cloned()
+            .collect();
+        for file_name in removed {
+            if let Some(synced_file) = self.files.remove(&file_name) {
+                self.pending_deletions.push(synced_file.file_id);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Records that a generated file was uploaded. The new copy is not attached to any
+    /// vector store yet. The previously uploaded copy of the file, if any, is scheduled for
+    /// deletion, which must not happen before the new copy is attached in its place.
+    pub fn record_upload(&mut self, file_path: &Path, file_id: &str) -> Result<(), RepconError> {
+        let synced_file = SyncedFile {
+            file_id: file_id.to_string(),
+            sha256: file_sha256(file_path)?,
+            vector_store_id: None,
+        };
+        if let Some(previous) = self.files.insert(sync_file_name(file_path)?, synced_file) {
+            self.pending_deletions.push(previous.file_id);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the uploaded copy of a generated file is attached to the vector store.
+    pub fn is_attached(&self, file_path: &Path, vector_store_id: &str) -> bool {
+        sync_file_name(file_path)
+            .ok()
+            .and_then(|file_name| self.files.get(&file_name))
+            .is_some_and(|file| file.vector_store_id.as_deref() == Some(vector_store_id))
+    }
+
+    /// Records that the uploaded copies of generated files were attached to a vector store.
+    pub fn record_attach(&mut self, file_paths: &[PathBuf], vector_store_id: &str) {
+        for file_path in file_paths {
+            if let Some(file) = sync_file_name(file_path)
+                .ok()
+                .and_then(|file_name| self.files.get_mut(&file_name))
+            {
+                file.vector_store_id = Some(vector_store_id.to_string());
+            }
+        }
+        self.vector_store_id = Some(vector_store_id.to_string());
+    }
+
+    /// Forgets the vector store along with which files were attached to it,
+    /// so that a later sync with a vector store attaches every file again.
+    pub fn forget_vector_store(&mut self) {
+        self.vector_store_id = None;
+        for file in self.files.values_mut() {
+            file.vector_store_id = None;
+        }
+    }
+}
+
+/// Returns the key of a generated file in the state file.
+fn sync_file_name(file_path: &Path) -> Result<String, RepconError> {
+    file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| RepconError::InvalidPath {
+            path: file_path.to_path_buf(),
+            reason: "Invalid output file name".to_string(),
+        })
+}
+
+/// Calculates the SHA-256 of a file's content as lowercase hex.
+pub fn file_sha256(file_path: &Path) -> Result<String, RepconError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file_path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod sync_tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sync_plan_and_record_upload() -> io::Result<()> {
+        let dir = tempdir()?;
+        let output_1 = dir.path().join("output_1.txt");
+        let output_2 = dir.path().join("output_2.txt");
+        fs::write(&output_1, "unchanged")?;
+        fs::write(&output_2, "changed")?;
+
+        let mut state = SyncState::default();
+        state.record_upload(&output_1, "file-1")?;
+        fs::write(&output_2, "before")?;
+        state.record_upload(&output_2, "file-2")?;
+        state.files.insert(
+            "output_3.txt".to_string(),
+            SyncedFile {
+                file_id: "file-3".to_string(),
+                sha256: "0".repeat(64),
+                vector_store_id: None,
+            },
+        );
+        fs::write(&output_2, "after")?;
+
+        let plan = state.plan(&[output_1.clone(), output_2.clone()])?;
+        assert_eq!(plan.unchanged, vec![(output_1.clone(), "file-1".to_string())]);
+        assert_eq!(plan.changed, vec![output_2.clone()]);
+        assert_eq!(state.pending_deletions, vec!["file-3"]);
+
+        state.record_upload(&output_2, "file-4")?;
+        assert_eq!(state.pending_deletions, vec!["file-3", "file-2"]);
+        assert_eq!(state.files["output_2.txt"].file_id, "file-4");
+
+        // A file stays unattached until attaching it succeeds
+        assert!(!state.is_attached(&output_1, "vs_1"));
+        state.record_attach(std::slice::from_ref(&output_1), "vs_1");
+        assert!(state.is_attached(&output_1, "vs_1"));
+        assert!(!state.is_attached(&output_1, "vs_2"));
+        assert!(!state.is_attached(&output_2, "vs_1"));
+        assert_eq!(state.vector_store_id.as_deref(), Some("vs_1"));
+
+        let mut forgotten = state.clone();
+        forgotten.forget_vector_store();
+        assert_eq!(forgotten.vector_store_id, None);
+        assert!(!forgotten.is_attached(&output_1, "vs_1"));
+
+        let state_path = dir.path().join(DEFAULT_SYNC_STATE_FILE);
+        state.save(&state_path)?;
+        assert_eq!(SyncState::load(&state_path)?, state);
+        assert_eq!(
+            SyncState::load(&dir.path().join("missing.json"))?,
+            SyncState::default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_sha256() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("output_1.txt");
+        fs::write(&file_path, "hello")?;
+        assert_eq!(
+            file_sha256(&file_path)?,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        Ok(())
+    }
+}
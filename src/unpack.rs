@@ -0,0 +1,469 @@
+use crate::{is_output_file_name, manifest_file_name, Manifest, PageFormat, RepconError};
+use flate2::read::GzDecoder;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+const FILE_NAME_PREFIX: &str = "# repcon_file_name: ";
+const PAGE_NUMBER_PREFIX: &str = "# repcon_page_number: ";
+const START_PREFIX: &str = "// START OF CODE BLOCK: ";
+
+/// What [`unpack`] restored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnpackReport {
+    /// Paths of the restored files, relative to the output directory.
+    pub files: Vec<PathBuf>,
+    /// Files listed as skipped in the manifest. Their partial content is not restored.
+    pub skipped_files: Vec<PathBuf>,
+    /// Number of output chunks read.
+    pub chunk_count: u64,
+}
+
+/// Where the unpacker is in the pages of an output chunk.
+enum State {
+    /// Between two pages.
+    Between,
+    /// After the file name line of a page header.
+    FileName(String),
+    /// After the page number line of a page header.
+    PageNumber(String, u64),
+    /// Inside the code block of a page. `footer_lines` counts the lines read so far
+    /// that may be the page footer, which are written to the file if they are not.
+    Page {
+        path: String,
+        writer: BufWriter<File>,
+        footer_lines: u8,
+    },
+}
+
+/// Restores the files of the pages of output chunks, read one after another.
+struct Unpacker<'a> {
+    output_directory: &'a Path,
+    state: State,
+    /// The last page that was started, to check that continued files are read in order.
+    last_page: Option<(String, u64)>,
+    report: UnpackReport,
+}
+
+impl<'a> Unpacker<'a> {
+    fn new(output_directory: &'a Path) -> Self {
+        Self {
+            output_directory,
+            state: State::Between,
+            last_page: None,
+            report: UnpackReport::default(),
+        }
+    }
+
+    /// Reads an output chunk. Pages do not span chunks, so the chunk must end after a page.
+    fn read_chunk(&mut self, name: &str, reader: impl BufRead) -> Result<(), RepconError> {
+        self.report.chunk_count += 1;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidData => {
+                    RepconError::Encoding(format!("{} is not valid UTF-8", name))
+                }
+                _ => RepconError::Io(e),
+            })?;
+            self.read_line(line, &format!("{}:{}", name, index + 1))?;
+        }
+        match std::mem::replace(&mut self.state, State::Between) {
+            State::Between => Ok(()),
+            State::Page {
+                mut writer,
+                footer_lines: 1..,
+                ..
+            } => Ok(writer.flush()?),
+            State::FileName(path) | State::PageNumber(path, _) | State::Page { path, .. } => Err(
+                RepconError::Encoding(format!("{} ends in the middle of a page of {}", name, path)),
+            ),
+        }
+    }
+
+    fn read_line(&mut self, line: String, location: &str) -> Result<(), RepconError> {
+        let unexpected = |line: &str| {
+            RepconError::Encoding(format!("{}: Unexpected line: {:?}", location, line))
+        };
+        self.state = match std::mem::replace(&mut self.state, State::Between) {
+            State::Between if line.is_empty() => State::Between,
+            State::Between => match line.strip_prefix(FILE_NAME_PREFIX) {
+                Some(path) => State::FileName(path.to_string()),
+                None => return Err(unexpected(&line)),
+            },
+            State::FileName(path) => {
+                match line
+                    .strip_prefix(PAGE_NUMBER_PREFIX)
+                    .and_then(|number| number.parse().ok())
+                    .filter(|&number| number >= 1)
+                {
+                    Some(page_number) => State::PageNumber(path, page_number),
+                    None => return Err(unexpected(&line)),
+                }
+            }
+            State::PageNumber(path, page_number) => {
+                if line.strip_prefix(START_PREFIX) != Some(path.as_str()) {
+                    return Err(unexpected(&line));
+                }
+                let writer = self.start_page(&path, page_number, location)?;
+                State::Page {
+                    path,
+                    writer,
+                    footer_lines: 0,
+                }
+            }
+            State::Page {
+                path,
+                mut writer,
+                footer_lines,
+            } => {
+                let footer = PageFormat::create_page_footer(&path);
+                let footer_line = footer.trim_end_matches('\n');
+                if footer_lines == 0 && line == footer_line {
+                    State::Page {
+                        path,
+                        writer,
+                        footer_lines: 1,
+                    }
+                } else if footer_lines == 1 && line.is_empty() {
+                    State::Page {
+                        path,
+                        writer,
+                        footer_lines: 2,
+                    }
+                } else if footer_lines > 0 && line.starts_with(FILE_NAME_PREFIX) {
+                    // The footer was followed by the header of the next page
+                    writer.flush()?;
+                    return self.read_line(line, location);
+                } else if footer_lines > 0 {
+                    // The footer lines were content of the file
+                    writer.write_all(footer_line.as_bytes())?;
+                    writer.write_all(if footer_lines == 2 { b"\n\n" } else { b"\n" })?;
+                    self.state = State::Page {
+                        path,
+                        writer,
+                        footer_lines: 0,
+                    };
+                    return self.read_line(line, location);
+                } else {
+                    writer.write_all(line.as_bytes())?;
+                    writer.write_all(b"\n")?;
+                    State::Page {
+                        path,
+                        writer,
+                        footer_lines: 0,
+                    }
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// Creates the file of a first page, or opens the file of a continued page for appending.
+    fn start_page(
+        &mut self,
+        path: &str,
+        page_number: u64,
+        location: &str,
+    ) -> Result<BufWriter<File>, RepconError> {
+        let relative_path = checked_relative_path(path)?;
+        let file_path = self.output_directory.join(&relative_path);
+        let file = if page_number == 1 {
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.report.files.push(relative_path);
+            File::create(&file_path)?
+        } else {
+            match &self.last_page {
+                Some((last_path, last_number))
+                    if last_path == path && *last_number + 1 == page_number => {}
+                _ => {
+                    return Err(RepconError::Encoding(format!(
+                        "{}: Page {} of {} does not follow page {}",
+                        location,
+                        page_number,
+                        path,
+                        page_number - 1
+                    )))
+                }
+            }
+            OpenOptions::new().append(true).open(&file_path)?
+        };
+        self.last_page = Some((path.to_string(), page_number));
+        Ok(BufWriter::new(file))
+    }
+
+    /// Removes the restored files that the manifest lists as skipped: only the lines before
+    /// the first invalid one of a non-text file are packed.
+    fn finish(mut self, manifest: Option<Manifest>) -> Result<UnpackReport, RepconError> {
+        for skipped in manifest.iter().flat_map(|manifest| &manifest.skipped_files) {
+            if let Some(index) = self.report.files.iter().position(|f| *f == skipped.path) {
+                fs::remove_file(self.output_directory.join(&skipped.path))?;
+                self.report.files.remove(index);
+                self.report.skipped_files.push(skipped.path.clone());
+            }
+        }
+        Ok(self.report)
+    }
+}
+
+/// Returns the path shown in a page header if it stays inside the output directory.
+fn checked_relative_path(path: &str) -> Result<PathBuf, RepconError> {
+    let relative_path = PathBuf::from(path);
+    let mut components = relative_path.components().peekable();
+    if components.peek().is_none()
+        || !components.all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(RepconError::InvalidPath {
+            path: relative_path,
+            reason: "Not a relative path inside the output directory".to_string(),
+        });
+    }
+    Ok(relative_path)
+}
+
+/// Returns the number of an output file named after `output_name`.
+fn chunk_number(file_name: &str, output_name: &str) -> Option<u64> {
+    if !is_output_file_name(file_name, output_name) {
+        return None;
+    }
+    let file_name = file_name.rsplit(['/', '\\']).next()?;
+    file_name[output_name.len() + 1..file_name.len() - ".txt".len()]
+        .parse()
+        .ok()
+}
+
+/// Sorts the output file names of a directory or archive by number, leaving out other names.
+fn sorted_chunk_names<'a>(names: impl Iterator<Item = &'a str>, output_name: &str) -> Vec<&'a str> {
+    let mut chunks: Vec<(u64, &str)> = names
+        .filter_map(|name| chunk_number(name, output_name).map(|number| (number, name)))
+        .collect();
+    chunks.sort();
+    chunks.into_iter().map(|(_, name)| name).collect()
+}
+
+/// Restores the packed files from the output files `{output_name}_N.txt` of a directory,
+/// or from an archive written with `--bundle`, into `output_directory`.
+///
+/// Files are restored from the page headers and footers of the output chunks.
+/// Line endings are written as `\n`, and every restored file ends with a newline.
+/// If the manifest is found, files it lists as skipped are not restored.
+/// Paths that would leave the output directory are rejected.
+pub fn unpack(
+    input: &Path,
+    output_name: &str,
+    output_directory: &Path,
+) -> Result<UnpackReport, RepconError> {
+    let invalid_input = |reason: &str| RepconError::InvalidPath {
+        path: input.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    if !input.exists() {
+        return Err(invalid_input("No such file or directory"));
+    }
+    let input_name = input.to_string_lossy();
+    fs::create_dir_all(output_directory)?;
+    let mut unpacker = Unpacker::new(output_directory);
+    let mut manifest_json = None;
+
+    if input.is_dir() {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(input)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        for name in sorted_chunk_names(names.iter().map(String::as_str), output_name) {
+            let file = File::open(input.join(name))?;
+            unpacker.read_chunk(name, BufReader::new(file))?;
+        }
+        let manifest_path = input.join(manifest_file_name(output_name));
+        if manifest_path.is_file() {
+            manifest_json = Some(fs::read_to_string(manifest_path)?);
+        }
+    } else if input_name.ends_with(".zip") {
+        let mut archive = ZipArchive::new(File::open(input)?).map_err(io::Error::from)?;
+        let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        for name in sorted_chunk_names(names.iter().map(String::as_str), output_name) {
+            let entry = archive.by_name(name).map_err(io::Error::from)?;
+            unpacker.read_chunk(name, BufReader::new(entry))?;
+        }
+        if let Ok(mut entry) = archive.by_name(&manifest_file_name(output_name)) {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            manifest_json = Some(json);
+        };
+    } else if input_name.ends_with(".tar.gz") || input_name.ends_with(".tgz") {
+        // The entries of a tar archive can only be read in order, as written by the bundle
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(input)?));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if chunk_number(&name, output_name).is_some() {
+                unpacker.read_chunk(&name, BufReader::new(&mut entry))?;
+            } else if name == manifest_file_name(output_name) {
+                let mut json = String::new();
+                entry.read_to_string(&mut json)?;
+                manifest_json = Some(json);
+            }
+        }
+    } else {
+        return Err(invalid_input(
+            "Expected a directory of output files, a .zip or a .tar.gz file",
+        ));
+    }
+
+    if unpacker.report.chunk_count == 0 {
+        return Err(invalid_input(&format!(
+            "No output files named {}_N.txt",
+            output_name
+        )));
+    }
+    let manifest = manifest_json
+        .map(|json| Manifest::from_json(&json))
+        .transpose()?;
+    unpacker.finish(manifest)
+}
+
+#[cfg(test)]
+mod unpack_tests {
+    use super::*;
+    use crate::{BundleFormat, BundleSink, Repcon};
+    use tempfile::tempdir;
+
+    /// Creates a repository whose files span several output chunks of 200 bytes.
+    fn create_repo(root: &Path) -> io::Result<()> {
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("README.md"), "# Title\n\nText\n")?;
+        fs::write(root.join("src/main.rs"), "fn main() {}\n".repeat(30))?;
+        // Content that looks like a page footer is restored as content
+        fs::write(
+            root.join("src/footer.txt"),
+            "before\n// END OF CODE BLOCK: src/footer.txt\n\nafter\n",
+        )?;
+        fs::write(root.join("image.bin"), [b'a', b'\n', 0xff, 0xfe])?;
+        Ok(())
+    }
+
+    fn repcon(root: &Path, output_directory: &Path) -> Result<Repcon, RepconError> {
+        Repcon::builder()
+            .root(root)
+            .max_file_size(200)
+            .output_directory(output_directory)
+            .build()
+    }
+
+    fn assert_restored(root: &Path, unpacked: &Path, report: &UnpackReport) -> io::Result<()> {
+        assert_eq!(
+            report.files,
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("src/footer.txt"),
+                PathBuf::from("src/main.rs")
+            ]
+        );
+        for file in &report.files {
+            assert_eq!(fs::read(unpacked.join(file))?, fs::read(root.join(file))?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_output_directory() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        create_repo(&root)?;
+        let output = dir.path().join("output");
+        let report = repcon(&root, &output)?.run()?;
+        assert!(report.chunk_count > 2);
+
+        // Without a manifest, the packed lines of the non-text file are restored
+        let unpacked = dir.path().join("unpacked");
+        let unpacked_report = unpack(&output, "output", &unpacked)?;
+        assert_eq!(unpacked_report.chunk_count, report.chunk_count);
+        assert_eq!(fs::read_to_string(unpacked.join("image.bin"))?, "a\n");
+
+        fs::write(
+            output.join("output_manifest.json"),
+            Manifest::from_report(&report).to_json()?,
+        )?;
+        let unpacked = dir.path().join("unpacked_with_manifest");
+        let unpacked_report = unpack(&output, "output", &unpacked)?;
+        assert_restored(&root, &unpacked, &unpacked_report)?;
+        assert_eq!(
+            unpacked_report.skipped_files,
+            vec![PathBuf::from("image.bin")]
+        );
+        assert!(!unpacked.join("image.bin").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_bundles() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        create_repo(&root)?;
+        for format in [BundleFormat::Zip, BundleFormat::TarGz] {
+            let path = dir.path().join(format!("output.{}", format.extension()));
+            let mut sink = BundleSink::create(&path, format)?;
+            let report = repcon(&root, dir.path())?.run_into(&mut sink)?;
+            let manifest = Manifest::from_report(&report).to_json()?;
+            sink.add_file(&manifest_file_name("output"), manifest.as_bytes())?;
+            sink.finish()?;
+
+            let unpacked = dir.path().join(format!("unpacked_{}", format.extension()));
+            let unpacked_report = unpack(&path, "output", &unpacked)?;
+            assert_restored(&root, &unpacked, &unpacked_report)?;
+            assert_eq!(unpacked_report.chunk_count, report.chunk_count);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_rejects_paths_outside_the_output_directory() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let output = dir.path().join("output");
+        fs::create_dir_all(&output)?;
+        for path in ["../escaped.txt", "/tmp/escaped.txt"] {
+            fs::write(
+                output.join("output_1.txt"),
+                format!(
+                    "{}content\n{}",
+                    PageFormat::create_page_header(path, 1),
+                    PageFormat::create_page_footer(path)
+                ),
+            )?;
+            let result = unpack(&output, "output", &dir.path().join("unpacked"));
+            assert!(matches!(result, Err(RepconError::InvalidPath { .. })));
+        }
+        assert!(!dir.path().join("escaped.txt").exists());
+
+        fs::write(output.join("output_1.txt"), "not a page\n")?;
+        let result = unpack(&output, "output", &dir.path().join("unpacked"));
+        assert!(matches!(result, Err(RepconError::Encoding(_))));
+        assert!(matches!(
+            unpack(&output, "other", &dir.path().join("unpacked")),
+            Err(RepconError::InvalidPath { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_rejects_page_zero() -> Result<(), RepconError> {
+        let dir = tempdir()?;
+        let output = dir.path().join("output");
+        fs::create_dir_all(&output)?;
+        fs::write(
+            output.join("output_1.txt"),
+            format!(
+                "{}content\n{}",
+                PageFormat::create_page_header("src/main.rs", 0),
+                PageFormat::create_page_footer("src/main.rs")
+            ),
+        )?;
+        let result = unpack(&output, "output", &dir.path().join("unpacked"));
+        assert!(matches!(result, Err(RepconError::Encoding(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+use crate::anthropic::{AnthropicUploader, ANTHROPIC_MAX_FILE_SIZE};
+use crate::gemini::{GeminiUploader, GEMINI_MAX_FILE_SIZE};
+use crate::openai::{
+    parse_retry_after, OpenAiClient, Retry, RetryPolicy, UploadError, UploadedFile,
+    OPENAI_MAX_FILE_SIZE,
+};
+use crate::s3::{S3Uploader, S3_MAX_FILE_SIZE};
+use crate::vector_store::DEFAULT_INDEXING_TIMEOUT;
+use crate::webdav::WebDavUploader;
+use crate::RepconError;
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, StatusCode};
+use std::ffi::OsStr;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio_util::io::ReaderStream;
+
+/// Interval between two checks of the indexing status of files attached to a vector store.
+const COLLECTION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Receives the progress of an upload: the file being uploaded,
+/// the number of bytes sent so far and the size of the file.
+/// A file is read again from the start when its upload is retried.
+pub type UploadProgress = Arc<dyn Fn(&Path, u64, u64) + Send + Sync>;
+
+/// A destination the output files can be published to.
+///
+/// Uploaded files are identified by the ID returned by `upload`,
+/// which is the file ID for OpenAI and the object key or file name for the other backends.
+#[async_trait]
+pub trait Uploader: Send + Sync {
+    /// Describes the destination in messages, e.g. `s3://bucket/prefix`.
+    fn destination(&self) -> String;
+
+    /// Uploads a file, streaming its contents and reporting the bytes sent to `progress`.
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError>;
+
+    /// Deletes an uploaded file.
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError>;
+
+    /// Lists the uploaded files.
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError>;
+
+    /// Adds uploaded files to a named collection, e.g. an OpenAI vector store.
+    /// Returns `RepconError::Unsupported` if the destination has no collections.
+    async fn attach_to_collection(
+        &self,
+        collection: &str,
+        file_ids: &[String],
+    ) -> Result<(), RepconError> {
+        let _ = (collection, file_ids);
+        Err(RepconError::Unsupported(format!(
+            "{} does not support collections",
+            self.destination()
+        )))
+    }
+}
+
+/// A destination given with `--upload-to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadDestination {
+    /// The OpenAI Files API.
+    OpenAi,
+    /// The Anthropic Files API.
+    Anthropic,
+    /// The Gemini Files API.
+    Gemini,
+    /// An S3-compatible bucket, with a key prefix that may be empty.
+    S3 { bucket: String, prefix: String },
+    /// A WebDAV collection, given as its HTTP(S) URL.
+    WebDav { url: String },
+    /// A local directory.
+    Directory(PathBuf),
+}
+impl UploadDestination {
+    /// Parses a destination: `openai`, `anthropic`, `gemini`, `s3://BUCKET[/PREFIX]`,
+    /// `webdav+https://HOST/PATH` (or `webdav+http://`),
+    /// or a local directory given as a path or a `file://` URL.
+    pub fn parse(spec: &str) -> Result<Self, RepconError> {
+        match spec {
+            "openai" => return Ok(Self::OpenAi),
+            "anthropic" => return Ok(Self::Anthropic),
+            "gemini" => return Ok(Self::Gemini),
+            _ => {}
+        }
+        if let Some(location) = spec.strip_prefix("s3://") {
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            if bucket.is_empty() {
+                return Err(RepconError::Config(format!(
+                    "Missing bucket name in upload destination: {}",
+                    spec
+                )));
+            }
+            return Ok(Self::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+            });
+        }
+        if let Some(url) = spec.strip_prefix("webdav+") {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(RepconError::Config(format!(
+                    "Invalid WebDAV upload destination: {}",
+                    spec
+                )));
+            }
+            return Ok(Self::WebDav {
+                url: url.trim_end_matches('/').to_string(),
+            });
+        }
+        let path = spec.strip_prefix("file://").unwrap_or(spec);
+        Ok(Self::Directory(PathBuf::from(path)))
+    }
+
+    /// Returns the maximum size of a file accepted by the destination, in bytes,
+    /// or `None` if the destination has no particular limit.
+    pub fn max_file_size(&self) -> Option<u64> {
+        match self {
+            Self::OpenAi => Some(OPENAI_MAX_FILE_SIZE),
+            Self::Anthropic => Some(ANTHROPIC_MAX_FILE_SIZE),
+            Self::Gemini => Some(GEMINI_MAX_FILE_SIZE),
+            Self::S3 { .. } => Some(S3_MAX_FILE_SIZE),
+            Self::WebDav { .. } | Self::Directory(_) => None,
+        }
+    }
+
+    /// Creates the uploader for the destination.
+    /// `openai_client` is used for the `openai` destination, and is required for it.
+    /// The Anthropic and Gemini uploaders retry failed requests with `retry_policy`.
+    pub fn uploader(
+        &self,
+        openai_client: Option<OpenAiClient>,
+        retry_policy: &RetryPolicy,
+    ) -> Result<Box<dyn Uploader>, RepconError> {
+        match self {
+            Self::OpenAi => openai_client
+                .map(|client| Box::new(client) as Box<dyn Uploader>)
+                .ok_or_else(|| {
+                    RepconError::Config(
+                        "API key not specified and not found in environment.".to_string(),
+                    )
+                }),
+            Self::Anthropic => Ok(Box::new(AnthropicUploader::from_env(retry_policy.clone())?)),
+            Self::Gemini => Ok(Box::new(GeminiUploader::from_env(retry_policy.clone())?)),
+            Self::S3 { bucket, prefix } => Ok(Box::new(S3Uploader::from_env(bucket, prefix)?)),
+            Self::WebDav { url } => Ok(Box::new(WebDavUploader::from_env(url)?)),
+            Self::Directory(path) => Ok(Box::new(DirectoryUploader::new(path))),
+        }
+    }
+}
+
+/// Uploads several files with an uploader, at most `concurrency` at a time,
+/// e.g. with an [`OpenAiClient`] sharing its connections between the uploads.
+///
+/// Every file is attempted even if other uploads fail. `on_result` is called for each file
+/// as soon as it and all the files before it are done, so results are reported in the
+/// order of `file_paths` regardless of which upload finishes first.
+/// Returns the results in the same order.
+pub async fn upload_files_with<F>(
+    uploader: &dyn Uploader,
+    file_paths: &[PathBuf],
+    concurrency: usize,
+    progress: Option<UploadProgress>,
+    mut on_result: F,
+) -> Vec<(PathBuf, Result<UploadedFile, UploadError>)>
+where
+    F: FnMut(&Path, &Result<UploadedFile, UploadError>),
+{
+    let progress = progress.as_ref();
+    let mut uploads = stream::iter(file_paths)
+        .map(|file_path| async move {
+            (
+                file_path.clone(),
+                uploader.upload(file_path, progress).await,
+            )
+        })
+        .buffered(concurrency.max(1));
+
+    let mut results = Vec::with_capacity(file_paths.len());
+    while let Some((file_path, result)) = uploads.next().await {
+        on_result(&file_path, &result);
+        results.push((file_path, result));
+    }
+    results
+}
+
+/// Returns the name a file is uploaded under by the object store and directory backends.
+pub(crate) fn upload_name(file_path: &Path) -> Result<String, UploadError> {
+    file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| UploadError::new(file_path, "Invalid file name"))
+}
+
+/// Opens a file as a request body that is read while it is sent, so that memory use
+/// does not depend on the size of the file.
+/// Returns the body and the size of the file.
+pub(crate) async fn file_body(
+    file_path: &Path,
+    progress: Option<&UploadProgress>,
+) -> io::Result<(reqwest::Body, u64)> {
+    let file = tokio::fs::File::open(file_path).await?;
+    let size = file.metadata().await?.len();
+    let file_path = file_path.to_path_buf();
+    let progress = progress.cloned();
+    let mut sent = 0;
+    let chunks = ReaderStream::new(file).inspect_ok(move |chunk| {
+        sent += chunk.len() as u64;
+        if let Some(progress) = &progress {
+            progress(&file_path, sent, size);
+        }
+    });
+    Ok((reqwest::Body::wrap_stream(chunks), size))
+}
+
+/// A failed request to an object store or WebDAV server.
+pub(crate) struct RequestFailure {
+    pub status: Option<u16>,
+    pub code: Option<String>,
+    pub message: String,
+}
+impl RequestFailure {
+    /// Constructs a `RequestFailure` for a failure that happened before a response was received.
+    pub(crate) fn new(message: impl ToString) -> Self {
+        Self {
+            status: None,
+            code: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Converts the failure of an upload request to an `UploadError`.
+    pub(crate) fn into_upload_error(self, file_path: &Path) -> UploadError {
+        UploadError {
+            file_path: file_path.to_path_buf(),
+            status: self.status,
+            code: self.code,
+            message: self.message,
+        }
+    }
+
+    /// Converts the failure to a `RepconError` describing what was being done.
+    pub(crate) fn into_error(self, action: &str) -> RepconError {
+        RepconError::Request {
+            action: action.to_string(),
+            status: self.status,
+            code: self.code,
+            message: self.message,
+        }
+    }
+}
+
+/// Sends a request to an LLM provider, retrying the failures selected by `retry`
+/// according to the retry policy, like [`OpenAiClient`] does.
+/// The request is rebuilt by `build_request` for every attempt, and `failure` describes
+/// a non-successful response from its status and body.
+/// Returns the headers and body of the successful response, or the failure of the last attempt.
+pub(crate) async fn send_with_retry<F, Fut>(
+    retry_policy: &RetryPolicy,
+    retry: Retry,
+    build_request: F,
+    failure: fn(StatusCode, &str) -> RequestFailure,
+) -> Result<(HeaderMap, String), RequestFailure>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<RequestBuilder, RequestFailure>>,
+{
+    let mut attempt = 1;
+    loop {
+        let (last_failure, retryable, retry_after) = match build_request().await?.send().await {
+            Err(e) => (RequestFailure::new(&e), retry.retries_error(&e), None),
+            Ok(response) => {
+                let status = response.status();
+                let headers = response.headers().clone();
+                match response.text().await {
+                    Ok(body) if status.is_success() => return Ok((headers, body)),
+                    Ok(body) => (
+                        failure(status, &body),
+                        retry.retries_status(status.as_u16()),
+                        parse_retry_after(&headers),
+                    ),
+                    Err(e) => (RequestFailure::new(e), retry == Retry::Always, None),
+                }
+            }
+        };
+        if !retryable || attempt >= retry_policy.max_attempts {
+            return Err(last_failure);
+        }
+        tokio::time::sleep(retry_policy.delay(attempt, retry_after)).await;
+        attempt += 1;
+    }
+}
+
+/// Percent-encodes a string, leaving only unreserved characters (and `/` if `keep_slash` is set)
+/// as they are.
+pub(crate) fn percent_encode(text: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decodes a percent-encoded string. Invalid escapes are kept as they are.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Replaces the predefined XML entities of a text node.
+pub(crate) fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[async_trait]
+impl Uploader for OpenAiClient {
+    fn destination(&self) -> String {
+        "openai".to_string()
+    }
+
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        self.upload_file_with_progress(file_path, "assistants", progress)
+            .await
+    }
+
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError> {
+        self.delete_file(file_id).await
+    }
+
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError> {
+        self.list_files("assistants").await
+    }
+
+    /// Attaches the files to the vector store with the given name or ID, creating it if needed.
+    /// Returns an error if any file could not be indexed.
+    async fn attach_to_collection(
+        &self,
+        collection: &str,
+        file_ids: &[String],
+    ) -> Result<(), RepconError> {
+        let (vector_store, _) = self.find_or_create_vector_store(collection).await?;
+        let failures = self
+            .attach_files_to_vector_store(
+                &vector_store.id,
+                file_ids,
+                COLLECTION_POLL_INTERVAL,
+                DEFAULT_INDEXING_TIMEOUT,
+            )
+            .await?;
+        if !failures.is_empty() {
+            return Err(RepconError::IndexingFailed {
+                vector_store_id: vector_store.id,
+                failed: failures.len(),
+                total: file_ids.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Copies the output files into a local directory, e.g. a mounted network share.
+/// Files are identified by their file name.
+pub struct DirectoryUploader {
+    directory: PathBuf,
+}
+impl DirectoryUploader {
+    /// Constructs a new `DirectoryUploader`. The directory is created on the first upload.
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl Uploader for DirectoryUploader {
+    fn destination(&self) -> String {
+        self.directory.display().to_string()
+    }
+
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        let file_name = upload_name(file_path)?;
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| UploadError::new(file_path, e))?;
+        let bytes = tokio::fs::copy(file_path, self.directory.join(&file_name))
+            .await
+            .map_err(|e| UploadError::new(file_path, e))?;
+        if let Some(progress) = progress {
+            progress(file_path, bytes, bytes);
+        }
+        Ok(UploadedFile {
+            id: file_name.clone(),
+            filename: file_name,
+            bytes,
+            created_at: 0,
+        })
+    }
+
+    /// Deletes a file of the directory. The ID must be a plain file name,
+    /// so that no file outside the directory can be deleted.
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError> {
+        if Path::new(file_id).file_name() != Some(OsStr::new(file_id)) {
+            return Err(RepconError::InvalidPath {
+                path: PathBuf::from(file_id),
+                reason: "Not a file name".to_string(),
+            });
+        }
+        tokio::fs::remove_file(self.directory.join(file_id)).await?;
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError> {
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            files.push(UploadedFile {
+                id: file_name.clone(),
+                filename: file_name,
+                bytes: metadata.len(),
+                created_at: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |duration| duration.as_secs()),
+            });
+        }
+        files.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod uploader_tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_upload_destination() -> io::Result<()> {
+        assert_eq!(
+            UploadDestination::parse("openai")?,
+            UploadDestination::OpenAi
+        );
+        assert_eq!(
+            UploadDestination::parse("anthropic")?.max_file_size(),
+            Some(ANTHROPIC_MAX_FILE_SIZE)
+        );
+        assert_eq!(
+            UploadDestination::parse("s3://my-bucket")?.max_file_size(),
+            Some(S3_MAX_FILE_SIZE)
+        );
+        assert_eq!(
+            UploadDestination::parse("s3://my-bucket/packs/repo/")?,
+            UploadDestination::S3 {
+                bucket: "my-bucket".to_string(),
+                prefix: "packs/repo".to_string(),
+            }
+        );
+        assert_eq!(
+            UploadDestination::parse("webdav+https://dav.example.com/packs/")?,
+            UploadDestination::WebDav {
+                url: "https://dav.example.com/packs".to_string(),
+            }
+        );
+        assert_eq!(
+            UploadDestination::parse("file:///srv/packs")?,
+            UploadDestination::Directory(PathBuf::from("/srv/packs"))
+        );
+        assert!(UploadDestination::parse("s3://").is_err());
+        assert!(UploadDestination::parse("webdav+ftp://host").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        assert_eq!(
+            percent_encode("packs/my repo/a+b.txt", true),
+            "packs/my%20repo/a%2Bb.txt"
+        );
+        assert_eq!(percent_encode("a/b", false), "a%2Fb");
+        assert_eq!(
+            percent_decode("/dav/my%20repo/output_1.txt"),
+            "/dav/my repo/output_1.txt"
+        );
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[tokio::test]
+    async fn test_directory_uploader() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_paths: Vec<PathBuf> = (1..=2)
+            .map(|i| {
+                let file_path = dir.path().join(format!("output_{}.txt", i));
+                fs::write(&file_path, "hello").unwrap();
+                file_path
+            })
+            .collect();
+        let uploader = DirectoryUploader::new(&dir.path().join("published"));
+
+        let results = upload_files_with(&uploader, &file_paths, 2, None, |_, _| {}).await;
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+        let file_ids: Vec<String> = uploader.list().await?.into_iter().map(|f| f.id).collect();
+        assert_eq!(file_ids, vec!["output_1.txt", "output_2.txt"]);
+
+        uploader.delete("output_1.txt").await?;
+        assert_eq!(uploader.list().await?.len(), 1);
+        for file_id in ["../output_2.txt", "/tmp/output_2.txt", "..", "."] {
+            let error = uploader.delete(file_id).await.unwrap_err();
+            assert!(matches!(error, RepconError::InvalidPath { .. }));
+        }
+        assert!(dir.path().join("output_2.txt").exists());
+        let error = uploader
+            .attach_to_collection("docs", &file_ids)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RepconError::Unsupported(_)));
+        Ok(())
+    }
+}
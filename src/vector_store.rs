@@ -0,0 +1,337 @@
+use crate::openai::{ListPage, OpenAiClient, OpenAiError};
+use crate::RepconError;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Maximum number of files attached by a single file batch.
+const FILE_BATCH_SIZE: usize = 500;
+
+/// Default time to wait for the files of a batch to be indexed.
+pub const DEFAULT_INDEXING_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Maximum number of objects returned by a list request.
+const LIST_LIMIT: usize = 100;
+
+/// A vector store object returned by the OpenAI Vector Stores API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VectorStore {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// A file of a vector store that could not be indexed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileIndexingFailure {
+    pub file_id: String,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// A vector store file batch.
+#[derive(Deserialize)]
+struct FileBatch {
+    id: String,
+    status: String,
+}
+
+/// A file attached to a vector store.
+#[derive(Deserialize)]
+struct VectorStoreFile {
+    id: String,
+    last_error: Option<LastError>,
+}
+
+/// The reason a vector store file could not be indexed.
+#[derive(Deserialize)]
+struct LastError {
+    code: Option<String>,
+    message: Option<String>,
+}
+
+impl OpenAiClient {
+    /// Returns the vector store with the given ID, or the first vector store with the given name.
+    /// A vector store with that name is created if there is none.
+    ///
+    /// # Arguments
+    /// * `name_or_id` - A vector store ID (`vs_...`) or name.
+    ///
+    /// # Returns
+    /// The vector store, and whether it was created.
+    pub async fn find_or_create_vector_store(
+        &self,
+        name_or_id: &str,
+    ) -> Result<(VectorStore, bool), RepconError> {
+        if name_or_id.starts_with("vs_") {
+            let vector_store = self
+                .get_json(
+                    &format!("get vector store {}", name_or_id),
+                    &format!("vector_stores/{}", name_or_id),
+                )
+                .await?;
+            return Ok((vector_store, false));
+        }
+
+        let mut after: Option<String> = None;
+        loop {
+            let mut path = format!("vector_stores?limit={}", LIST_LIMIT);
+            if let Some(after) = &after {
+                path.push_str(&format!("&after={}", after));
+            }
+            let page: ListPage<VectorStore> = self.get_json("list vector stores", &path).await?;
+            if let Some(vector_store) = page
+                .data
+                .into_iter()
+                .find(|vector_store| vector_store.name.as_deref() == Some(name_or_id))
+            {
+                return Ok((vector_store, false));
+            }
+            match page.last_id {
+                Some(last_id) if page.has_more => after = Some(last_id),
+                _ => break,
+            }
+        }
+
+        let vector_store = self
+            .post_json(
+                &format!("create vector store {}", name_or_id),
+                "vector_stores",
+                &json!({ "name": name_or_id }),
+            )
+            .await?;
+        Ok((vector_store, true))
+    }
+
+    /// Attaches files to a vector store and waits until they are indexed.
+    ///
+    /// The files are attached with the file batch API, at most 500 per batch,
+    /// and each batch is polled every `poll_interval` until it is no longer in progress.
+    ///
+    /// # Returns
+    /// The files that could not be indexed, or an error if a request failed,
+    /// a batch was cancelled or the files were not indexed within `timeout`.
+    pub async fn attach_files_to_vector_store(
+        &self,
+        vector_store_id: &str,
+        file_ids: &[String],
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<Vec<FileIndexingFailure>, RepconError> {
+        let deadline = Instant::now() + timeout;
+        let mut failures = Vec::new();
+        for batch_file_ids in file_ids.chunks(FILE_BATCH_SIZE) {
+            let action = format!("attach files to vector store {}", vector_store_id);
+            let mut batch: FileBatch = self
+                .post_json(
+                    &action,
+                    &format!("vector_stores/{}/file_batches", vector_store_id),
+                    &json!({ "file_ids": batch_file_ids }),
+                )
+                .await?;
+            while batch.status == "in_progress" {
+                if Instant::now() + poll_interval > deadline {
+                    return Err(OpenAiError::new(
+                        &action,
+                        format!(
+                            "File batch {} is still in progress after {}s",
+                            batch.id,
+                            timeout.as_secs()
+                        ),
+                    )
+                    .into());
+                }
+                tokio::time::sleep(poll_interval).await;
+                batch = self
+                    .get_json(
+                        &format!("get file batch {}", batch.id),
+                        &format!(
+                            "vector_stores/{}/file_batches/{}",
+                            vector_store_id, batch.id
+                        ),
+                    )
+                    .await?;
+            }
+            if batch.status == "cancelled" {
+                return Err(OpenAiError::new(
+                    &action,
+                    format!("File batch {} was cancelled", batch.id),
+                )
+                .into());
+            }
+            failures.extend(self.failed_batch_files(vector_store_id, &batch.id).await?);
+        }
+        Ok(failures)
+    }
+
+    /// Removes a file from a vector store. The file itself is not deleted.
+    pub async fn remove_file_from_vector_store(
+        &self,
+        vector_store_id: &str,
+        file_id: &str,
+    ) -> Result<(), RepconError> {
+        self.delete(
+            &format!(
+                "remove file {} from vector store {}",
+                file_id, vector_store_id
+            ),
+            &format!("vector_stores/{}/files/{}", vector_store_id, file_id),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Lists the files of a batch that could not be indexed.
+    async fn failed_batch_files(
+        &self,
+        vector_store_id: &str,
+        batch_id: &str,
+    ) -> Result<Vec<FileIndexingFailure>, OpenAiError> {
+        let mut failures = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut path = format!(
+                "vector_stores/{}/file_batches/{}/files?filter=failed&limit={}",
+                vector_store_id, batch_id, LIST_LIMIT
+            );
+            if let Some(after) = &after {
+                path.push_str(&format!("&after={}", after));
+            }
+            let page: ListPage<VectorStoreFile> = self
+                .get_json(&format!("list failed files of batch {}", batch_id), &path)
+                .await?;
+            failures.extend(page.data.into_iter().map(|file| {
+                let (code, message) = match file.last_error {
+                    Some(error) => (error.code, error.message.unwrap_or_default()),
+                    None => (None, String::new()),
+                };
+                FileIndexingFailure {
+                    file_id: file.id,
+                    code,
+                    message,
+                }
+            }));
+            match page.last_id {
+                Some(last_id) if page.has_more => after = Some(last_id),
+                _ => return Ok(failures),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod vector_store_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::openai::{OpenAiConfig, RetryPolicy};
+
+    fn test_client(server: &MockServer) -> OpenAiClient {
+        let config = OpenAiConfig {
+            api_base: server.url.clone(),
+            retry_policy: RetryPolicy {
+                max_attempts: 1,
+                ..RetryPolicy::default()
+            },
+            ..OpenAiConfig::new("test-key")
+        };
+        OpenAiClient::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_find_or_create_vector_store() {
+        let server = MockServer::start(|request| match request.method.as_str() {
+            "GET" if request.path.contains("after=vs_2") => MockResponse::new(
+                200,
+                r#"{"data": [{"id": "vs_3", "name": "other"}], "has_more": false, "last_id": "vs_3"}"#,
+            ),
+            "GET" => MockResponse::new(
+                200,
+                r#"{"data": [{"id": "vs_1", "name": null}, {"id": "vs_2", "name": "docs"}], "has_more": true, "last_id": "vs_2"}"#,
+            ),
+            _ => MockResponse::new(200, r#"{"id": "vs_new", "name": "repo"}"#),
+        })
+        .await;
+        let client = test_client(&server);
+
+        let (vector_store, created) = client.find_or_create_vector_store("docs").await.unwrap();
+        assert_eq!(vector_store.id, "vs_2");
+        assert!(!created);
+
+        let (vector_store, created) = client.find_or_create_vector_store("repo").await.unwrap();
+        assert_eq!(vector_store.id, "vs_new");
+        assert!(created);
+
+        let requests = server.requests();
+        let create = requests.last().unwrap();
+        assert_eq!(create.method, "POST");
+        assert_eq!(create.path, "/vector_stores");
+        assert_eq!(create.header("openai-beta"), Some("assistants=v2"));
+        assert_eq!(String::from_utf8_lossy(&create.body), r#"{"name":"repo"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_attach_files_to_vector_store_reports_failures() {
+        let server = MockServer::start_sequence(vec![
+            MockResponse::new(200, r#"{"id": "vsfb_1", "status": "in_progress"}"#),
+            MockResponse::new(200, r#"{"id": "vsfb_1", "status": "completed"}"#),
+            MockResponse::new(
+                200,
+                r#"{"data": [{"id": "file-2", "status": "failed", "last_error": {"code": "unsupported_file", "message": "File type not supported"}}], "has_more": false, "last_id": "file-2"}"#,
+            ),
+        ])
+        .await;
+        let file_ids = vec!["file-1".to_string(), "file-2".to_string()];
+
+        let failures = test_client(&server)
+            .attach_files_to_vector_store(
+                "vs_1",
+                &file_ids,
+                Duration::from_millis(1),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            failures,
+            vec![FileIndexingFailure {
+                file_id: "file-2".to_string(),
+                code: Some("unsupported_file".to_string()),
+                message: "File type not supported".to_string(),
+            }]
+        );
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/vector_stores/vs_1/file_batches");
+        assert_eq!(
+            String::from_utf8_lossy(&requests[0].body),
+            r#"{"file_ids":["file-1","file-2"]}"#
+        );
+        assert_eq!(requests[1].path, "/vector_stores/vs_1/file_batches/vsfb_1");
+        assert_eq!(
+            requests[2].path,
+            "/vector_stores/vs_1/file_batches/vsfb_1/files?filter=failed&limit=100"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attach_files_to_vector_store_times_out() {
+        let server = MockServer::start(|_| {
+            MockResponse::new(200, r#"{"id": "vsfb_1", "status": "in_progress"}"#)
+        })
+        .await;
+        let file_ids = vec!["file-1".to_string()];
+
+        let error = test_client(&server)
+            .attach_files_to_vector_store(
+                "vs_1",
+                &file_ids,
+                Duration::from_millis(5),
+                Duration::from_millis(30),
+            )
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("still in progress"));
+        assert!(server.requests().len() <= 8);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::openai::{UploadError, UploadedFile};
+use crate::uploader::{
+    file_body, percent_decode, percent_encode, upload_name, xml_unescape, RequestFailure,
+    UploadProgress, Uploader,
+};
+use crate::RepconError;
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Method, StatusCode};
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+/// Timeout of a single request to the WebDAV server.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Properties requested when listing the collection.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/></prop></propfind>"#;
+
+/// Uploads the output files to a WebDAV collection, e.g. a Nextcloud folder.
+/// Files are identified by their file name.
+pub struct WebDavUploader {
+    http: reqwest::Client,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    patterns: MultistatusPatterns,
+}
+impl WebDavUploader {
+    /// Constructs a new `WebDavUploader` for the collection at `url`,
+    /// authenticating with HTTP basic authentication if a username is given.
+    pub fn new(
+        url: &str,
+        username: Option<String>,
+        password: Option<String>,
+    ) -> Result<Self, RepconError> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| RepconError::Config(e.to_string()))?;
+        Ok(Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            username,
+            password,
+            patterns: MultistatusPatterns::new(),
+        })
+    }
+
+    /// Constructs a new `WebDavUploader` authenticated with the
+    /// `WEBDAV_USERNAME` and `WEBDAV_PASSWORD` environment variables, if set.
+    pub fn from_env(url: &str) -> Result<Self, RepconError> {
+        Self::new(
+            url,
+            env::var("WEBDAV_USERNAME").ok(),
+            env::var("WEBDAV_PASSWORD").ok(),
+        )
+    }
+
+    /// Returns the URL of a file in the collection.
+    fn file_url(&self, name: &str) -> String {
+        format!("{}/{}", self.url, percent_encode(name, false))
+    }
+
+    /// Sends a request and returns the status and body of the response.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        configure: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    ) -> Result<(StatusCode, String), RequestFailure> {
+        let mut request = self.http.request(method, url);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let response = configure(request)
+            .send()
+            .await
+            .map_err(RequestFailure::new)?;
+        let status = response.status();
+        let body = response.text().await.map_err(RequestFailure::new)?;
+        Ok((status, body))
+    }
+
+    /// Uploads the contents of a file, returning an error if the server rejects it.
+    /// A conflict is returned as a status, since it means that the collection is missing.
+    /// Returns the status and the number of bytes sent.
+    async fn put(
+        &self,
+        url: &str,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<(StatusCode, u64), RequestFailure> {
+        let (contents, size) = file_body(file_path, progress)
+            .await
+            .map_err(RequestFailure::new)?;
+        let (status, body) = self
+            .send(Method::PUT, url, |request| {
+                request.header(CONTENT_LENGTH, size).body(contents)
+            })
+            .await?;
+        if !status.is_success() && status != StatusCode::CONFLICT {
+            return Err(failure(status, &body));
+        }
+        Ok((status, size))
+    }
+}
+
+/// Regular expressions matching the elements of a `PROPFIND` multistatus response,
+/// with any namespace prefix.
+struct MultistatusPatterns {
+    response: Regex,
+    href: Regex,
+    length: Regex,
+    collection: Regex,
+}
+impl MultistatusPatterns {
+    fn new() -> Self {
+        Self {
+            response: Regex::new(
+                r"(?s)<(?:[A-Za-z0-9]+:)?response\b[^>]*>(.*?)</(?:[A-Za-z0-9]+:)?response>",
+            )
+            .unwrap(),
+            href: Regex::new(r"<(?:[A-Za-z0-9]+:)?href>([^<]*)</(?:[A-Za-z0-9]+:)?href>").unwrap(),
+            length: Regex::new(
+                r"<(?:[A-Za-z0-9]+:)?getcontentlength>(\d+)</(?:[A-Za-z0-9]+:)?getcontentlength>",
+            )
+            .unwrap(),
+            collection: Regex::new(r"<(?:[A-Za-z0-9]+:)?collection\s*/?>").unwrap(),
+        }
+    }
+}
+
+/// Describes a non-successful response.
+fn failure(status: StatusCode, body: &str) -> RequestFailure {
+    RequestFailure {
+        status: Some(status.as_u16()),
+        code: None,
+        message: status
+            .canonical_reason()
+            .map(str::to_string)
+            .unwrap_or_else(|| body.trim().to_string()),
+    }
+}
+
+#[async_trait]
+impl Uploader for WebDavUploader {
+    fn destination(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Uploads a file with `PUT`.
+    /// If the collection does not exist yet, it is created with `MKCOL` and the upload is retried.
+    async fn upload(
+        &self,
+        file_path: &Path,
+        progress: Option<&UploadProgress>,
+    ) -> Result<UploadedFile, UploadError> {
+        let name = upload_name(file_path)?;
+        let url = self.file_url(&name);
+
+        let (mut status, mut bytes) = self
+            .put(&url, file_path, progress)
+            .await
+            .map_err(|e| e.into_upload_error(file_path))?;
+        if status == StatusCode::CONFLICT {
+            let collection_url = format!("{}/", self.url);
+            let (mkcol_status, body) = self
+                .send(
+                    Method::from_bytes(b"MKCOL").unwrap(),
+                    &collection_url,
+                    |r| r,
+                )
+                .await
+                .map_err(|e| e.into_upload_error(file_path))?;
+            // 405 means the collection already exists
+            if !mkcol_status.is_success() && mkcol_status != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(failure(mkcol_status, &body).into_upload_error(file_path));
+            }
+            (status, bytes) = self
+                .put(&url, file_path, progress)
+                .await
+                .map_err(|e| e.into_upload_error(file_path))?;
+        }
+        if status == StatusCode::CONFLICT {
+            return Err(failure(status, "").into_upload_error(file_path));
+        }
+
+        Ok(UploadedFile {
+            id: name.clone(),
+            filename: name,
+            bytes,
+            created_at: 0,
+        })
+    }
+
+    async fn delete(&self, file_id: &str) -> Result<(), RepconError> {
+        let (status, body) = self
+            .send(Method::DELETE, &self.file_url(file_id), |r| r)
+            .await
+            .map_err(|e| e.into_error(&format!("delete {}", file_id)))?;
+        if !status.is_success() {
+            return Err(failure(status, &body).into_error(&format!("delete {}", file_id)));
+        }
+        Ok(())
+    }
+
+    /// Lists the files of the collection with `PROPFIND`. Subcollections are skipped.
+    async fn list(&self) -> Result<Vec<UploadedFile>, RepconError> {
+        let action = format!("list {}", self.url);
+        let (status, body) = self
+            .send(
+                Method::from_bytes(b"PROPFIND").unwrap(),
+                &format!("{}/", self.url),
+                |request| {
+                    request
+                        .header("Depth", "1")
+                        .header("Content-Type", "application/xml")
+                        .body(PROPFIND_BODY)
+                },
+            )
+            .await
+            .map_err(|e| e.into_error(&action))?;
+        if !status.is_success() {
+            return Err(failure(status, &body).into_error(&action));
+        }
+
+        let patterns = &self.patterns;
+        let mut files = Vec::new();
+        for response in patterns.response.captures_iter(&body) {
+            let response = &response[1];
+            if patterns.collection.is_match(response) {
+                continue;
+            }
+            let Some(href) = patterns.href.captures(response) else {
+                continue;
+            };
+            let href = percent_decode(&xml_unescape(&href[1]));
+            let name = href
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            files.push(UploadedFile {
+                id: name.to_string(),
+                filename: name.to_string(),
+                bytes: patterns
+                    .length
+                    .captures(response)
+                    .and_then(|length| length[1].parse().ok())
+                    .unwrap_or(0),
+                created_at: 0,
+            });
+        }
+        files.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod webdav_tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use std::fs;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tempfile::tempdir;
+
+    const MULTISTATUS: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/dav/packs/</d:href><d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>
+  <d:response><d:href>/dav/packs/output%201.txt</d:href><d:propstat><d:prop><d:resourcetype/><d:getcontentlength>5</d:getcontentlength></d:prop></d:propstat></d:response>
+</d:multistatus>"#;
+
+    #[tokio::test]
+    async fn test_webdav_uploader_creates_collection() -> io::Result<()> {
+        let collection_exists = AtomicBool::new(false);
+        let server = MockServer::start(move |request| match request.method.as_str() {
+            "PUT" if !collection_exists.load(Ordering::SeqCst) => MockResponse::new(409, ""),
+            "MKCOL" => {
+                collection_exists.store(true, Ordering::SeqCst);
+                MockResponse::new(201, "")
+            }
+            "PROPFIND" => MockResponse::new(207, MULTISTATUS),
+            _ => MockResponse::new(201, ""),
+        })
+        .await;
+        let uploader = WebDavUploader::new(
+            &format!("{}/dav/packs/", server.url),
+            Some("user".to_string()),
+            Some("secret".to_string()),
+        )?;
+        let dir = tempdir()?;
+        let file_path = dir.path().join("output 1.txt");
+        fs::write(&file_path, "hello")?;
+
+        let uploaded_file = uploader.upload(&file_path, None).await.unwrap();
+        assert_eq!(uploaded_file.id, "output 1.txt");
+        assert_eq!(uploaded_file.bytes, 5);
+        let files = uploader.list().await?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].id, "output 1.txt");
+        assert_eq!(files[0].bytes, 5);
+
+        let requests = server.requests();
+        let methods: Vec<&str> = requests.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(methods, vec!["PUT", "MKCOL", "PUT", "PROPFIND"]);
+        assert_eq!(requests[0].path, "/dav/packs/output%201.txt");
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Basic dXNlcjpzZWNyZXQ=")
+        );
+        assert_eq!(requests[3].header("depth"), Some("1"));
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
 
 #[test]
 fn test_output_directory_specified() {
@@ -44,3 +48,553 @@ fn test_max_file_size_specified() {
         .expect("Failed to execute command");
     assert!(output.status.success());
 }
+
+#[test]
+fn test_multiple_roots_specified() {
+    let output = Command::new("cargo")
+        .args(["run", "src", "assets=tests/assets", "-o", "tests/output"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_files_from_stdin_specified() {
+    let mut child = Command::new("cargo")
+        .args(["run", "--", "--files-from", "-", "-o", "tests/output"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to execute command");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"src/main.rs\nsrc/lib.rs\n")
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn test_files_from_conflicts_with_ignore_patterns() {
+    for args in [["-i", "*.rs"], ["-r", ".repconignore"]] {
+        let output = Command::new("cargo")
+            .args(["run", "--", "--files-from", "-", "-o", "tests/output"])
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to execute command");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn test_dry_run_conflicts_with_stdout() {
+    let output = Command::new("cargo")
+        .args(["run", "--", ".", "--stdout", "--dry-run"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_sort_order_specified() {
+    let output = Command::new("cargo")
+        .args(["run", ".", "--sort", "git", "-o", "tests/output"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+}
+
+/// Starts a stand-in for the OpenAI Files API on a random local port.
+/// Answers every request with a file object and sends the request heads through the channel.
+fn start_openai_stand_in() -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut reader = BufReader::new(stream.unwrap());
+            let mut head = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                if line.trim_end().is_empty() {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response_body =
+                r#"{"id": "file-test", "object": "file", "bytes": 1, "filename": "output_1.txt"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response_body.len(),
+                response_body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            sender.send(head).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+#[test]
+fn test_upload_to_api_base_specified() {
+    let (api_base, requests) = start_openai_stand_in();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "src",
+            "-o",
+            "tests/output/api_base",
+            "-u",
+            "test-key",
+            "--api-base",
+            &api_base,
+            "--openai-organization",
+            "org-test",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let request_head = requests.recv().unwrap().to_lowercase();
+    assert!(request_head.starts_with("post /v1/files "));
+    assert!(request_head.contains("authorization: bearer test-key"));
+    assert!(request_head.contains("openai-organization: org-test"));
+}
+
+#[test]
+fn test_sync_skips_unchanged_files() {
+    let (api_base, requests) = start_openai_stand_in();
+    let sync = || {
+        Command::new("cargo")
+            .args([
+                "run",
+                "src",
+                "-o",
+                "tests/output/sync",
+                "-u",
+                "test-key",
+                "--api-base",
+                &api_base,
+                "--sync",
+                "--sync-state",
+                "tests/output/sync/state.json",
+            ])
+            .output()
+            .expect("Failed to execute command")
+    };
+    let _ = std::fs::remove_file("tests/output/sync/state.json");
+
+    assert!(sync().status.success());
+    let request_head = requests.recv().unwrap().to_lowercase();
+    assert!(request_head.starts_with("post /v1/files "));
+    while requests.try_recv().is_ok() {}
+
+    let output = sync();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("0 new or changed"));
+    assert!(requests.try_recv().is_err());
+}
+
+#[test]
+fn test_files_delete_dry_run_specified() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "files",
+            "delete",
+            "file-abc123",
+            "--dry-run",
+            "--api-key",
+            "test-key",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Would delete file-abc123"));
+}
+
+#[test]
+fn test_files_prune_only_deletes_tracked_files() {
+    let state = "tests/output/prune/state.json";
+    std::fs::create_dir_all("tests/output/prune").unwrap();
+    std::fs::write(
+        state,
+        r#"{"files": {"output_1.txt": {"file_id": "file-kept", "sha256": ""}}, "pending_deletions": ["file-old"]}"#,
+    )
+    .unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "files",
+            "prune",
+            "--sync-state",
+            state,
+            "--dry-run",
+            "--api-key",
+            "test-key",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Would delete file-old"));
+    assert!(!stdout.contains("file-kept"));
+}
+
+#[test]
+fn test_directory_named_like_a_subcommand() {
+    let dir = "tests/output/subcommand_names";
+    std::fs::create_dir_all(format!("{}/stats", dir)).unwrap();
+    std::fs::write(format!("{}/stats/notes.txt", dir), "notes\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new("cargo")
+            .current_dir(dir)
+            .args(["run", "--"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["stats"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("run `repcon ./stats`"));
+
+    for args in [
+        &["./stats", "-o", "out_path"][..],
+        &["-o", "out_dashes", "--", "stats"],
+    ] {
+        assert!(run(args).status.success());
+        let out = args[args.iter().position(|&arg| arg == "-o").unwrap() + 1];
+        let content = std::fs::read_to_string(format!("{}/{}/output_1.txt", dir, out)).unwrap();
+        assert!(content.contains("// START OF CODE BLOCK: notes.txt"));
+    }
+}
+
+#[test]
+fn test_upload_and_upload_to_openai_conflict() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "src",
+            "-o",
+            "tests/output",
+            "-u",
+            "test-key",
+            "--upload-to",
+            "openai",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("-u already uploads"));
+}
+
+#[test]
+fn test_upload_to_directory_specified() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "src",
+            "-o",
+            "tests/output/upload_to",
+            "--upload-to",
+            "tests/output/upload_to_published",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(std::path::Path::new("tests/output/upload_to_published/output_1.txt").exists());
+    assert!(std::path::Path::new("tests/output/upload_to/output_manifest.json").is_file());
+    assert!(
+        !std::path::Path::new("tests/output/upload_to_published/output_manifest.json").exists()
+    );
+}
+
+#[test]
+fn test_size_limit_exceeded_exit_code() {
+    let root = "tests/output/size_limit";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/large.txt", root), "x".repeat(2 * 1024 * 1024)).unwrap();
+    let output = Command::new("cargo")
+        .args(["run", root, "-f", "1", "-s", "1", "-o", "tests/output"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error: The total size of the files"));
+}
+
+#[test]
+fn test_missing_paths_exit_code() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "-o", "tests/output"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Give the paths to the repositories"));
+}
+
+#[test]
+fn test_missing_root_exit_code() {
+    let output = Command::new("cargo")
+        .args(["run", "missing-root", "-o", "tests/output"])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Error: Root directory not found: missing-root"));
+}
+
+#[test]
+fn test_stdout_specified() {
+    let root = "tests/output/stdout";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/hello.txt", root), "Hello, world!\n").unwrap();
+    let output = Command::new("cargo")
+        .args(["run", "--", root, "--stdout"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("// START OF CODE BLOCK: hello.txt\nHello, world!\n"));
+    assert!(!stdout.contains("Total size"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Total size"));
+}
+
+#[test]
+fn test_bundle_specified() {
+    let root = "tests/output/bundle";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/hello.txt", root), "Hello, world!\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            root,
+            "--bundle",
+            "tar.gz",
+            "-n",
+            "bundle",
+            "-o",
+            "tests/output/bundle_output",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(std::path::Path::new("tests/output/bundle_output/bundle.tar.gz").is_file());
+    assert!(!std::path::Path::new("tests/output/bundle_output/bundle_1.txt").exists());
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "unpack",
+            "tests/output/bundle_output/bundle.tar.gz",
+            "-n",
+            "bundle",
+            "-o",
+            "tests/output/bundle_unpacked",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string("tests/output/bundle_unpacked/hello.txt").unwrap(),
+        "Hello, world!\n"
+    );
+}
+
+#[test]
+fn test_bundle_size_limit_keeps_previous_bundle() {
+    let root = "tests/output/bundle_size_limit";
+    let bundle = "tests/output/bundle_size_limit_output/output.zip";
+    std::fs::create_dir_all("tests/output/bundle_size_limit_output").unwrap();
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(bundle, "previous bundle").unwrap();
+    std::fs::write(format!("{}/large.txt", root), "x".repeat(2 * 1024 * 1024)).unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            root,
+            "--bundle",
+            "zip",
+            "-f",
+            "1",
+            "-s",
+            "1",
+            "-o",
+            "tests/output/bundle_size_limit_output",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(std::fs::read_to_string(bundle).unwrap(), "previous bundle");
+}
+
+#[test]
+fn test_bundle_and_upload_to_api_conflict() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "src",
+            "-o",
+            "tests/output",
+            "--bundle",
+            "zip",
+            "--upload-to",
+            "anthropic",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--bundle cannot be used with `--upload-to anthropic`"));
+}
+
+#[test]
+fn test_dry_run_specified() {
+    let root = "tests/output/dry_run";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/hello.txt", root), "Hello, world!\n").unwrap();
+    std::fs::write(format!("{}/debug.log", root), "log\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            root,
+            "--dry-run",
+            "-i",
+            "*.log",
+            "-o",
+            "tests/output/dry_run_output",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Included files (1):\n  hello.txt (14 B)"));
+    assert!(stdout.contains("debug.log: ignore pattern `*.log`"));
+    assert!(stdout.contains("Output files (1 of at most 20):\n  output_1.txt"));
+    assert!(!std::path::Path::new("tests/output/dry_run_output").exists());
+}
+
+#[test]
+fn test_explain_specified() {
+    let root = "tests/output/explain";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/.repconignore", root), "# Logs\n*.log\n").unwrap();
+    std::fs::write(format!("{}/debug.log", root), "log\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "explain",
+            "tests/output/explain/debug.log",
+            "--root",
+            root,
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(
+        "tests/output/explain/debug.log is excluded by `*.log` in tests/output/explain/.repconignore:2"
+    ));
+}
+
+#[test]
+fn test_stats_json_specified() {
+    let root = "tests/output/stats";
+    std::fs::create_dir_all(format!("{}/src", root)).unwrap();
+    std::fs::write(format!("{}/src/main.rs", root), "fn main() {}\n").unwrap();
+    std::fs::write(format!("{}/README.md", root), "# Title\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "stats",
+            root,
+            "--json",
+            "--no-redact",
+            "-s",
+            "1",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["total"]["files"], 2);
+    assert_eq!(stats["total"]["lines"], 2);
+    assert_eq!(stats["languages"][0]["name"], "Rust");
+    assert_eq!(stats["directories"][0]["name"], "src");
+    assert_eq!(stats["projected_chunks"], 1);
+}
+
+#[test]
+fn test_estimate_cost_specified() {
+    let root = "tests/output/estimate";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/hello.txt", root), "Hello, world!\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            root,
+            "--estimate-cost",
+            "--embedding-price",
+            "1000000",
+            "-o",
+            "tests/output/estimate_output",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cost estimate:\n  output_1.txt: "));
+    assert!(stderr.contains("at $1000000 per 1M tokens"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Cost estimate:"));
+    let manifest: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string("tests/output/estimate_output/output_manifest.json").unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        manifest["cost_estimate"]["chunks"][0]["name"],
+        "output_1.txt"
+    );
+}
+
+#[test]
+fn test_dry_run_estimate_cost_writes_nothing() {
+    let root = "tests/output/estimate_dry_run";
+    std::fs::create_dir_all(root).unwrap();
+    std::fs::write(format!("{}/hello.txt", root), "Hello, world!\n").unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            root,
+            "--dry-run",
+            "--estimate-cost",
+            "-o",
+            "tests/output/estimate_dry_run_output",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cost estimate:\n  output_1.txt: "));
+    assert!(!std::path::Path::new("tests/output/estimate_dry_run_output").exists());
+}